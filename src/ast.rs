@@ -1,3 +1,12 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+
+use crate::date;
+use crate::date::Unit;
+use crate::decimal;
+use crate::decimal::{ArithmeticError, Rounding};
+use crate::model::{Collation, Column, Columns, DataType, Record};
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -72,6 +81,21 @@ mod test {
         assert_eq!(tokenizer.next(), Some(String::from("2")));
     }
 
+    #[test]
+    fn tokenize_whitespace() {
+        let mut tokenizer = ExpressionTokenizer::new(String::from("gt(age, 30)  "));
+        let tokens: Vec<String> = tokenizer.by_ref().collect();
+        assert_eq!(tokens, ["gt", "(", "age", ",", "30", ")"]);
+        assert_eq!(tokenizer.next(), None);
+
+        assert_eq!(ExpressionTokenizer::new(String::from("   ")).next(), None);
+        assert_eq!(ExpressionTokenizer::new(String::new()).next(), None);
+
+        assert!(Expression::from_string("sum(age) ").is_ok());
+        assert!(Sort::from_string("sum(age) desc ").is_ok());
+        assert!(Expression::from_string("  ").is_err());
+    }
+
     #[test]
     fn tokenize_2() {
         let mut tokenizer = ExpressionTokenizer::new(String::from("add(sum(add(age, 1), 1)"));
@@ -107,6 +131,339 @@ mod test {
         assert_eq!(expr, Ok(expect_expr));
     }
 
+    #[test]
+    fn eval_aggregations() {
+        let columns = vec![String::from("name"), String::from("age")];
+        let records: Vec<Record> = vec![
+            vec![DataType::from_string("ni"), DataType::from_string("35")],
+            vec![DataType::from_string("si"), DataType::from_string("1.5")],
+            vec![DataType::from_string("ni"), DataType::from_string("x")],
+        ];
+        let refs: Vec<&Record> = records.iter().collect();
+
+        let eval = |s: &str| Expression::from_string(s).unwrap().eval(&columns, &refs);
+
+        assert_eq!(eval("sum(age)"), Ok(DataType::Decimal(365, 1)));
+        assert_eq!(eval("add(sum(add(age, 1)), 1)"), Ok(DataType::Decimal(405, 1)));
+        assert_eq!(eval("count()"), Ok(DataType::Int(3)));
+        assert_eq!(eval("values(name)"), Ok(DataType::from_string("ni, si")));
+        assert!(eval("name").is_err());
+        assert!(eval("sum(count())").is_err());
+        assert!(eval("sum(height)").is_err());
+        assert_eq!(
+            Expression::from_string("add(age, 99999999999999999999)"),
+            Err(ParseExpressionError {
+                message: "Number out of range"
+            })
+        );
+
+        assert_eq!(
+            Expression::from_string("name").unwrap().eval(&columns, &refs[..1]),
+            Ok(DataType::from_string("ni"))
+        );
+    }
+
+    #[test]
+    fn group_by_dimensions() {
+        let columns = vec![String::from("country"), String::from("age")];
+        let records: Vec<Record> = vec![
+            vec![DataType::from_string("swe"), DataType::from_string("35")],
+            vec![DataType::from_string("cn"), DataType::from_string("34")],
+            vec![DataType::from_string("swe"), DataType::from_string("1")],
+        ];
+        let refs: Vec<&Record> = records.iter().collect();
+        let exprs = vec![
+            Expression::from_string("sum(age)").unwrap(),
+            Expression::Count,
+        ];
+
+        let sort = Sort::default();
+        let rows = group_by(&columns, &refs, &[String::from("country")], &exprs, &sort).unwrap();
+
+        assert_eq!(
+            rows,
+            vec![
+                (vec![DataType::from_string("cn")], vec![DataType::Int(34), DataType::Int(1)]),
+                (vec![DataType::from_string("swe")], vec![DataType::Int(36), DataType::Int(2)]),
+            ]
+        );
+
+        let rows = group_by(&columns, &refs, &[], &exprs, &sort).unwrap();
+        assert_eq!(rows, vec![(vec![], vec![DataType::Int(70), DataType::Int(3)])]);
+
+        let rows = group_by(&columns, &[], &[], &exprs, &sort).unwrap();
+        assert_eq!(rows, vec![(vec![], vec![DataType::Int(0), DataType::Int(0)])]);
+
+        assert!(group_by(&columns, &refs, &[String::from("height")], &exprs, &sort).is_err());
+    }
+
+    #[test]
+    fn bucket_dimensions() {
+        let columns = vec![String::from("age")];
+        let records: Vec<Record> = ["35", "34", "1.5", "x", "40"]
+            .iter()
+            .map(|a| vec![DataType::from_string(a)])
+            .collect();
+        let refs: Vec<&Record> = records.iter().collect();
+        let buckets = |dim: &str| -> Vec<(String, String)> {
+            let dims = [String::from(dim)];
+            let exprs = [Expression::Count];
+            group_by(&columns, &refs, &dims, &exprs, &Sort::default())
+                .unwrap()
+                .into_iter()
+                .map(|(key, values)| (key[0].to_string(), values[0].to_string()))
+                .collect()
+        };
+        let pairs = |v: &[(&str, &str)]| -> Vec<(String, String)> {
+            v.iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            buckets("class(age, 10)"),
+            pairs(&[
+                ("", "1"),
+                ("0 <= x < 10", "1"),
+                ("30 <= x < 40", "2"),
+                ("40 <= x < 50", "1"),
+            ])
+        );
+        assert_eq!(
+            buckets("class(age, 0.5)")[1..3],
+            pairs(&[("1.5 <= x < 2.0", "1"), ("34.0 <= x < 34.5", "1")])
+        );
+        assert_eq!(
+            buckets("histogram(age, 4)"),
+            pairs(&[
+                ("", "1"),
+                ("1.5 <= x < 11.2", "1"),
+                ("30.6 <= x <= 40.3", "3")
+            ])
+        );
+
+        let class = Expression::from_string("class(age, 10)").unwrap();
+        assert_eq!(class.to_string(), "class(age, 10)");
+        assert_eq!(
+            class.eval_record(&columns, &vec![DataType::Int(-5)]),
+            Ok(DataType::from_string("-10 <= x < 0"))
+        );
+        assert!(class.eval(&columns, &refs).is_err());
+        assert!(Expression::from_string("class(age, 0)").is_err());
+        assert!(Expression::from_string("histogram(age, 1.5)").is_err());
+    }
+
+    #[test]
+    fn date_functions() {
+        let columns = vec![String::from("day"), String::from("at")];
+        let record = vec![
+            DataType::from_string("2023-05-31"),
+            DataType::from_string("2024-01-02 10:30"),
+        ];
+        let eval = |s: &str| {
+            let expr = Expression::from_string(s).unwrap();
+            assert_eq!(Expression::from_string(&expr.to_string()), Ok(expr.clone()));
+            expr.eval_record(&columns, &record).unwrap().to_string()
+        };
+
+        assert_eq!(eval("year(day)"), "2023");
+        assert_eq!(eval("quarter(day)"), "2");
+        assert_eq!(eval("month(at)"), "1");
+        assert_eq!(eval("week(day)"), "22");
+        assert_eq!(eval("day(day)"), "31");
+        assert_eq!(eval("weekday(day)"), "3");
+        assert_eq!(eval("date_trunc(month, day)"), "2023-05-01");
+        assert_eq!(eval("date_trunc(week, at)"), "2024-01-01 00:00:00");
+        assert_eq!(eval("date_add(month, 1, day)"), "2023-06-30");
+        assert_eq!(eval("date_add(hour, -11, day)"), "2023-05-30 13:00:00");
+        assert_eq!(eval("date_diff(day, day, at)"), "216");
+        assert_eq!(eval("date_format(at, %d.%m.%Y %H:%M)"), "02.01.2024 10:30");
+        assert_eq!(
+            eval("date_format(at, '%d, %m (%Y) + %H o''clock')"),
+            "02, 01 (2024) + 10 o'clock"
+        );
+        assert_eq!(eval("coalesce(year(add(day, 1)), 'none')"), "none");
+        assert_eq!(eval("year(add(day, 1))"), "");
+        assert_eq!(eval("today()").len(), 10);
+        assert!(Expression::from_string("date_trunc(fortnight, day)").is_err());
+        assert!(Expression::from_string("date_format(at, '%Y)").is_err());
+        let overflow = Expression::from_string("date_add(month, 9000000000000000000, day)");
+        assert_eq!(
+            overflow.unwrap().eval_record(&columns, &record),
+            Err(EvalError {
+                message: String::from(
+                    "date_add(month, 9000000000000000000, day): date out of range"
+                )
+            })
+        );
+        assert!(Expression::from_string("year(day, at)").is_err());
+        assert!(Expression::from_string("yesterday()").is_err());
+
+        let records = [
+            vec![DataType::from_string("2023-05-31"), DataType::Null],
+            vec![DataType::from_string("2023-05-02"), DataType::Null],
+            vec![DataType::from_string("2023-06-01"), DataType::Null],
+        ];
+        let refs: Vec<&Record> = records.iter().collect();
+        let dims = [String::from("date_trunc(month, day)")];
+        let exprs = [Expression::Count];
+        let rows = group_by(&columns, &refs, &dims, &exprs, &Sort::default()).unwrap();
+        let month = |s: &str| vec![DataType::from_string(s)];
+        assert_eq!(
+            rows,
+            vec![
+                (month("2023-05-01"), vec![DataType::Int(2)]),
+                (month("2023-06-01"), vec![DataType::Int(1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn conditional_functions() {
+        let columns = vec![String::from("age"), String::from("height")];
+        let records = [
+            vec![DataType::from_string("35"), DataType::from_string("1.80")],
+            vec![DataType::from_string("seventyone"), DataType::Null],
+            vec![DataType::from_string(""), DataType::from_string("1.5")],
+        ];
+        let eval = |s: &str, record: usize| {
+            let expr = Expression::from_string(s).unwrap();
+            assert_eq!(Expression::from_string(&expr.to_string()), Ok(expr.clone()));
+            expr.eval_record(&columns, &records[record])
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(eval("if(isnum(age), age, 0)", 0), "35");
+        assert_eq!(eval("if(isnum(age), age, 0)", 1), "0");
+        assert_eq!(eval("isnull(age)", 2), "true");
+        assert_eq!(eval("istext(age)", 1), "true");
+        assert_eq!(eval("istext(age)", 2), "false");
+        assert_eq!(eval("coalesce(height, age, 0)", 1), "seventyone");
+        assert_eq!(eval("coalesce(age, height)", 2), "1.5");
+        assert_eq!(eval("nullif(height, 1.8)", 0), "");
+        assert_eq!(eval("nullif(height, 1.8)", 2), "1.5");
+        assert_eq!(eval("gt(height, 1.8)", 0), "false");
+        assert_eq!(eval("ge(height, 1.8)", 0), "true");
+        assert_eq!(eval("eq(age, 'seventyone')", 1), "true");
+        assert_eq!(eval("ne(age, 35)", 0), "false");
+        assert_eq!(eval("lt(height, 2)", 1), "");
+        let case = "case when isnum(age) then age when isnull(age) then -1 else 0 end";
+        assert_eq!(eval(case, 0), "35");
+        assert_eq!(eval(case, 1), "0");
+        assert_eq!(eval(case, 2), "-1");
+        let nested = "case when height then add(1, case when age then 1 end) end";
+        assert_eq!(eval(nested, 2), "1");
+        assert_eq!(eval("add(case when isnum(age) then age end, 1)", 0), "36");
+
+        // only the branch taken is evaluated
+        let overflow = "add(9223372036854775807, 1)";
+        assert_eq!(eval(&format!("if(isnum(age), 1, {})", overflow), 0), "1");
+        let case = format!("case when isnum(age) then 1 when {} then 2 end", overflow);
+        assert_eq!(eval(&case, 0), "1");
+        assert_eq!(
+            eval(&format!("case when isnull(age) then {} end", overflow), 0),
+            ""
+        );
+        let expr = Expression::from_string(&format!("if(isnum(age), {}, 1)", overflow)).unwrap();
+        assert!(expr.eval_record(&columns, &records[0]).is_err());
+
+        let refs: Vec<&Record> = records.iter().collect();
+        let sum = Expression::from_string("sum(if(isnum(age), age, 0))").unwrap();
+        assert_eq!(sum.eval(&columns, &refs), Ok(DataType::Int(35)));
+
+        assert!(Expression::from_string("if(age, 1)").is_err());
+        assert!(Expression::from_string("coalesce()").is_err());
+        assert!(Expression::from_string("case when end").is_err());
+        assert!(Expression::from_string("case when age end").is_err());
+        assert!(Expression::from_string("case when age then 1").is_err());
+        assert!(Expression::from_string("case when then 1 end").is_err());
+    }
+
+    #[test]
+    fn columns_named_case() {
+        let columns = vec![String::from("case id"), String::from("case")];
+        let record = vec![DataType::Int(7), DataType::Int(1)];
+        let eval = |s: &str| {
+            let expr = Expression::from_string(s).unwrap();
+            assert_eq!(Expression::from_string(&expr.to_string()), Ok(expr.clone()));
+            expr.eval_record(&columns, &record).unwrap().to_string()
+        };
+
+        assert_eq!(eval("case id"), "7");
+        assert_eq!(eval("add(case, case id)"), "8");
+        assert_eq!(eval("case when case then case id else case end"), "7");
+        assert_eq!(eval("case  when case then 2 end"), "2");
+    }
+
+    #[test]
+    fn sort_and_limit_groups() {
+        let columns = vec![String::from("country"), String::from("age")];
+        let records: Vec<Record> = [
+            ("swe", "35"),
+            ("cn", "34"),
+            ("swe", "1"),
+            ("us", "2"),
+            ("us", "40"),
+        ]
+        .iter()
+        .map(|(c, a)| vec![DataType::from_string(c), DataType::from_string(a)])
+        .collect();
+        let refs: Vec<&Record> = records.iter().collect();
+        let dims = [String::from("country")];
+        let exprs = [Expression::from_string("sum(age)").unwrap()];
+        let countries = |sort: &Sort| -> Vec<String> {
+            group_by(&columns, &refs, &dims, &exprs, sort)
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key[0].to_string())
+                .collect()
+        };
+
+        let mut sort = Sort::from_string("sum(age) desc").unwrap();
+        assert_eq!(countries(&sort), vec!["us", "swe", "cn"]);
+        sort.limit = Some(2);
+        assert_eq!(countries(&sort), vec!["us", "swe"]);
+        sort.offset = 2;
+        assert_eq!(countries(&sort), vec!["cn"]);
+
+        // ties keep the order of the values
+        let sort = Sort {
+            limit: Some(2),
+            ..Sort::from_string("count desc").unwrap()
+        };
+        assert_eq!(countries(&sort), vec!["swe", "us"]);
+        assert_eq!(
+            countries(&Sort::from_string("value desc").unwrap()),
+            vec!["us", "swe", "cn"]
+        );
+        assert!(Sort::from_string("sum( desc").is_err());
+
+        let top = top_n(vec![5, 1, 4, 2, 3], |a: &i32, b: &i32| b.cmp(a), 1, Some(3));
+        assert_eq!(top, vec![4, 3, 2]);
+        assert_eq!(
+            top_n(vec![5, 1], |a: &i32, b: &i32| a.cmp(b), 0, Some(0)),
+            vec![]
+        );
+        assert_eq!(
+            top_n(vec![5, 1, 4], |a: &i32, b: &i32| a.cmp(b), 1, None),
+            vec![4, 5]
+        );
+    }
+
+    #[test]
+    fn parse_expression_depth() {
+        let nested = |n: usize| format!("{}1{}", "isnull(".repeat(n - 1), ")".repeat(n - 1));
+        assert!(Expression::from_string(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            Expression::from_string(&nested(MAX_DEPTH + 1)),
+            Err(ParseExpressionError {
+                message: "Expression nested too deeply"
+            })
+        );
+        assert!(Expression::from_string(&"add(".repeat(100_000)).is_err());
+    }
+
     #[test]
     fn parse_expression_3() {
         let expr_string = "add(var,1)";
@@ -123,54 +480,139 @@ mod test {
 impl std::fmt::Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         match self {
-            Expression::Add(a, b) => write!(
-                f,
-                "add({}, {})",
-                a.as_ref().to_string(),
-                b.as_ref().to_string()
-            ),
-            Expression::IntConst(i) => write!(f, "{}", i.to_string()),
+            Expression::Add(a, b) => write!(f, "add({}, {})", a, b),
+            Expression::StrConst(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expression::IntConst(i) => write!(f, "{}", i),
             Expression::Variable(s) => write!(f, "{}", s),
-            Expression::Sum(e) => write!(f, "sum({})", e.as_ref().to_string()),
+            Expression::Sum(e) => write!(f, "sum({})", e),
             Expression::Count => write!(f, "count()"),
             Expression::Values(s) => write!(f, "values({})", s),
-            Expression::DecConst(v, p) => {
-                if *p == 0 {
-                    write!(f, "{}", v)
-                } else {
-                    let dec_pos = *p as usize;
-                    let mut v_str = v.to_string();
-                    while v_str.len() <= dec_pos {
-                        v_str.insert(0, '0');
+            Expression::DecConst(v, p) => decimal::format_decimal(*v as i128, *p).fmt(f),
+            Expression::Class(e, w, p) => {
+                let width = decimal::format_decimal(*w as i128, *p);
+                write!(f, "class({}, {})", e, width)
+            }
+            Expression::Histogram(e, n) => write!(f, "histogram({}, {})", e, n),
+            Expression::Call(Function::Case, args) => {
+                write!(f, "case")?;
+                for part in args.chunks(2) {
+                    match part {
+                        [condition, value] => write!(f, " when {} then {}", condition, value)?,
+                        [value] => write!(f, " else {}", value)?,
+                        _ => (),
+                    }
+                }
+                write!(f, " end")
+            }
+            Expression::Call(function, args) => {
+                let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                match function {
+                    Function::DateTrunc(unit)
+                    | Function::DateAdd(unit)
+                    | Function::DateDiff(unit) => args.insert(0, unit.to_string()),
+                    Function::DateFormat(pattern) => {
+                        args.push(Expression::StrConst(pattern.clone()).to_string())
                     }
-                    v_str.insert(v_str.len() - dec_pos, '.');
-                    v_str.fmt(f)
+                    _ => (),
                 }
+                write!(f, "{}({})", function.name(), args.join(", "))
             }
         }
     }
 }
 
+/// how deeply expressions may be nested in calls, the parser
+/// recurses for each level, so this bounds its stack use
+const MAX_DEPTH: usize = 128;
+
+/// the words that separate the parts of a case expression
+const CASE_KEYWORDS: [&str; 4] = ["when", "then", "else", "end"];
+
 struct ExpressionTokenizer {
     s: String,
     index: usize,
+    /// how many case expressions are being parsed, inside of
+    /// them their keywords are tokens of their own
+    cases: usize,
+    /// the expressions being parsed that the next one is in
+    depth: usize,
 }
 
 impl ExpressionTokenizer {
     fn new(s: String) -> ExpressionTokenizer {
-        ExpressionTokenizer { s, index: 0 }
+        ExpressionTokenizer {
+            s,
+            index: 0,
+            cases: 0,
+            depth: 0,
+        }
     }
     fn is_token_separator(c: char) -> bool {
         let tokens = ['(', ')', '+', ','];
         tokens.contains(&c)
     }
+
+    /// the next token, without consuming it
+    fn peek(&mut self) -> Option<String> {
+        let index = self.index;
+        let token = self.next();
+        self.index = index;
+        token
+    }
+
+    /// a string literal as it is written, in single quotes with `''`
+    /// for a quote, an unterminated one runs to the end
+    fn quoted(&self) -> String {
+        let mut token = String::from("'");
+        let mut chars = self.s.chars().skip(self.index + 1).peekable();
+        while let Some(c) = chars.next() {
+            token.push(c);
+            if c == '\'' {
+                match chars.next_if_eq(&'\'') {
+                    Some(quote) => token.push(quote),
+                    None => break,
+                }
+            }
+        }
+        token
+    }
+
+    /// the length of the start of a token that is a token of its own,
+    /// a leading `case` followed by `when`, or up to a keyword inside
+    /// a case expression, so that a column can be named `case id`
+    fn keyword_split(&self, token: &str) -> usize {
+        if let Some(rest) = token.strip_prefix("case ") {
+            if rest.trim_start().split(' ').next() == Some("when") {
+                return "case".len();
+            }
+        }
+        let mut start = 0;
+        for word in token.split(' ') {
+            let end = start + word.len();
+            if self.cases > 0 && CASE_KEYWORDS.contains(&word) {
+                return if start == 0 { end } else { start };
+            }
+            start = end + 1;
+        }
+        token.len()
+    }
+
+    /// whether a `case` token starts a case expression rather than
+    /// being a column, that is whether `when` follows it
+    fn starts_case(&mut self) -> bool {
+        self.cases += 1;
+        let when = self.peek().as_deref() == Some("when");
+        self.cases -= 1;
+        when
+    }
 }
 
 impl std::iter::Iterator for ExpressionTokenizer {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.s.len() {
+        // the index counts characters, not bytes
+        if self.index >= self.s.chars().count() {
             return None;
         }
 
@@ -180,12 +622,18 @@ impl std::iter::Iterator for ExpressionTokenizer {
             .skip(self.index)
             .take_while(|c| *c == ' ')
             .collect();
-        self.index = self.index + ws.len();
-
-        let curr_char: Vec<char> = self.s.chars().skip(self.index).take(1).collect();
-        if ExpressionTokenizer::is_token_separator(curr_char[0]) {
-            self.index = self.index + 1;
-            return Some(String::from(curr_char[0]));
+        self.index += ws.len();
+
+        // nothing but whitespace was left
+        let curr_char = self.s.chars().nth(self.index)?;
+        if curr_char == '\'' {
+            let token = self.quoted();
+            self.index += token.chars().count();
+            return Some(token);
+        }
+        if ExpressionTokenizer::is_token_separator(curr_char) {
+            self.index += 1;
+            return Some(String::from(curr_char));
         }
 
         let token: String = self
@@ -194,8 +642,9 @@ impl std::iter::Iterator for ExpressionTokenizer {
             .skip(self.index)
             .take_while(|c| !ExpressionTokenizer::is_token_separator(*c))
             .collect();
+        let token = &token[..self.keyword_split(&token)];
 
-        self.index = self.index + token.len();
+        self.index += token.chars().count();
 
         Some(String::from(token.trim()))
     }
@@ -213,7 +662,7 @@ impl Expression {
         let parsed_expr = Expression::from_iter(&mut tkn)?;
 
         let tok = tkn.next();
-        if tok.as_deref() != None {
+        if tok.is_some() {
             return Err(ParseExpressionError {
                 message: "Expected end of input",
             });
@@ -222,8 +671,24 @@ impl Expression {
         Ok(parsed_expr)
     }
 
+    /// parses the next expression, an error when it is nested
+    /// more than `MAX_DEPTH` expressions deep
     fn from_iter<'a>(
         iter: &mut ExpressionTokenizer,
+    ) -> Result<Expression, ParseExpressionError<'a>> {
+        if iter.depth == MAX_DEPTH {
+            return Err(ParseExpressionError {
+                message: "Expression nested too deeply",
+            });
+        }
+        iter.depth += 1;
+        let parsed = Expression::parse_next(iter);
+        iter.depth -= 1;
+        parsed
+    }
+
+    fn parse_next<'a>(
+        iter: &mut ExpressionTokenizer,
     ) -> Result<Expression, ParseExpressionError<'a>> {
         let tok = iter.next();
         let as_deref = tok.as_deref();
@@ -312,30 +777,60 @@ impl Expression {
                     }
                 }
             }
+            Some("class") => {
+                let (expr, arg) = Expression::binning_args(iter)?;
+                match arg {
+                    Expression::IntConst(w) if w > 0 => Expression::Class(Box::new(expr), w, 0),
+                    Expression::DecConst(w, p) if w > 0 => Expression::Class(Box::new(expr), w, p),
+                    _ => {
+                        return Err(ParseExpressionError {
+                            message: "Expected a positive bucket width",
+                        })
+                    }
+                }
+            }
+            Some("histogram") => {
+                let (expr, arg) = Expression::binning_args(iter)?;
+                match arg {
+                    Expression::IntConst(n) if n > 0 => Expression::Histogram(Box::new(expr), n),
+                    _ => {
+                        return Err(ParseExpressionError {
+                            message: "Expected a positive number of buckets",
+                        })
+                    }
+                }
+            }
+            Some("case") if iter.starts_case() => {
+                iter.cases += 1;
+                let args = Expression::case_args(iter);
+                iter.cases -= 1;
+                Expression::Call(Function::Case, args?)
+            }
+            Some(s) if s.starts_with('\'') => Expression::string_literal(s)?,
             Some(s) => {
-                if s.len() == 0 {
+                if s.is_empty() {
                     return Err(ParseExpressionError {
                         message: "Expected non-empty string",
                     });
                 }
+                if iter.cases > 0 && CASE_KEYWORDS.contains(&s) {
+                    return Err(ParseExpressionError {
+                        message: "Unexpected keyword",
+                    });
+                }
 
-                let int_result: Result<i64, _> = s.parse();
-                match int_result {
-                    Ok(i) => return Ok(Expression::IntConst(i)),
-                    _ => (),
+                if iter.peek().as_deref() == Some("(") {
+                    let args = Expression::call_args(iter)?;
+                    return Function::call(s, args);
                 }
 
-                let float_result: Result<f64, _> = s.parse();
-                match float_result {
-                    Ok(_f) => {
-                        let parts: Vec<&str> = s.split(".").collect();
-                        let decimal_points = parts[1].len();
-                        let value = String::from(parts[0]) + parts[1];
-                        let num_result: Result<i64, _> = value.parse();
-                        match num_result {
-                            Ok(num) => return Ok(Expression::DecConst(num, decimal_points as u8)),
-                            _ => (),
-                        }
+                match DataType::from_string(s) {
+                    DataType::Int(i) => return Ok(Expression::IntConst(i)),
+                    DataType::Decimal(v, p) => return Ok(Expression::DecConst(v, p)),
+                    _ if decimal::is_numeric(s, &decimal::NumberFormat::default()) => {
+                        return Err(ParseExpressionError {
+                            message: "Number out of range",
+                        })
                     }
                     _ => (),
                 }
@@ -358,15 +853,662 @@ impl Expression {
             }
         };
 
-        return Ok(parsed_expression);
+        Ok(parsed_expression)
+    }
+
+    /// parses `(<expression>, ...)` of a function call
+    fn call_args<'a>(
+        iter: &mut ExpressionTokenizer,
+    ) -> Result<Vec<Expression>, ParseExpressionError<'a>> {
+        iter.next();
+        let mut args = vec![];
+        if iter.peek().as_deref() == Some(")") {
+            iter.next();
+            return Ok(args);
+        }
+        loop {
+            args.push(Expression::from_iter(iter)?);
+            match iter.next().as_deref() {
+                Some(",") => (),
+                Some(")") => return Ok(args),
+                _ => {
+                    return Err(ParseExpressionError {
+                        message: "Expected ',' or ')'",
+                    })
+                }
+            }
+        }
+    }
+
+    /// the string of a quoted token, with `''` unescaped
+    fn string_literal<'a>(token: &str) -> Result<Expression, ParseExpressionError<'a>> {
+        let mut s = String::new();
+        let mut chars = token.chars().skip(1);
+        while let Some(c) = chars.next() {
+            if c != '\'' {
+                s.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some(quote) => s.push(quote),
+                None => return Ok(Expression::StrConst(s)),
+            }
+        }
+        Err(ParseExpressionError {
+            message: "Expected closing quote",
+        })
+    }
+
+    /// parses `when <condition> then <expression> ... [else <expression>] end`
+    /// of a case expression, the arguments are the conditions and their
+    /// values in turn, then the else value if there is one
+    fn case_args<'a>(
+        iter: &mut ExpressionTokenizer,
+    ) -> Result<Vec<Expression>, ParseExpressionError<'a>> {
+        let mut args = vec![];
+        let mut tok = iter.next();
+        while tok.as_deref() == Some("when") {
+            args.push(Expression::from_iter(iter)?);
+            if iter.next().as_deref() != Some("then") {
+                return Err(ParseExpressionError {
+                    message: "Expected 'then'",
+                });
+            }
+            args.push(Expression::from_iter(iter)?);
+            tok = iter.next();
+        }
+        if args.is_empty() {
+            return Err(ParseExpressionError {
+                message: "Expected 'when'",
+            });
+        }
+        if tok.as_deref() == Some("else") {
+            args.push(Expression::from_iter(iter)?);
+            tok = iter.next();
+        }
+        match tok.as_deref() {
+            Some("end") => Ok(args),
+            _ => Err(ParseExpressionError {
+                message: "Expected 'end'",
+            }),
+        }
+    }
+
+    /// parses `(<expression>, <constant>)` of class and histogram
+    fn binning_args<'a>(
+        iter: &mut ExpressionTokenizer,
+    ) -> Result<(Expression, Expression), ParseExpressionError<'a>> {
+        let expect = |tok: Option<String>, expected: &str, message: &'a str| {
+            if tok.as_deref() == Some(expected) {
+                Ok(())
+            } else {
+                Err(ParseExpressionError { message })
+            }
+        };
+        expect(iter.next(), "(", "Expected '('")?;
+        let expr = Expression::from_iter(iter)?;
+        expect(iter.next(), ",", "Expected ','")?;
+        let arg = Expression::from_iter(iter)?;
+        expect(iter.next(), ")", "Expected ')'")?;
+        Ok((expr, arg))
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct EvalError {
+    pub message: String,
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Expression {
+    /// get the columns referenced by the expression
+    pub fn get_variables(&self) -> Vec<&ColReference> {
+        match self {
+            Expression::StrConst(_)
+            | Expression::IntConst(_)
+            | Expression::DecConst(_, _)
+            | Expression::Count => vec![],
+            Expression::Variable(col) | Expression::Values(col) => vec![col],
+            Expression::Add(a, b) => {
+                let mut vars = a.get_variables();
+                vars.extend(b.get_variables());
+                vars
+            }
+            Expression::Sum(e) | Expression::Class(e, _, _) | Expression::Histogram(e, _) => {
+                e.get_variables()
+            }
+            Expression::Call(_, args) => args.iter().flat_map(|a| a.get_variables()).collect(),
+        }
+    }
+
+    /// evaluates the expression as an aggregation over records
+    pub fn eval(&self, columns: &Columns, records: &[&Record]) -> Result<DataType, EvalError> {
+        match self {
+            Expression::StrConst(s) => Ok(DataType::String(s.as_str().into())),
+            Expression::IntConst(i) => Ok(DataType::Int(*i)),
+            Expression::DecConst(v, p) => Ok(DataType::Decimal(*v, *p)),
+            Expression::Add(a, b) => {
+                DataType::sum(a.eval(columns, records)?, b.eval(columns, records)?)
+                    .map_err(|e| self.arithmetic_error(e))
+            }
+            Expression::Count => Ok(DataType::Int(records.len() as i64)),
+            Expression::Sum(e) => {
+                let mut total = DataType::Int(0);
+                for record in records {
+                    let value = e.eval_record(columns, record)?;
+                    total = DataType::sum(total, value).map_err(|e| self.arithmetic_error(e))?;
+                }
+                Ok(total)
+            }
+            Expression::Values(col) => {
+                let index = Self::col_index(columns, col)?;
+                let values: BTreeSet<&DataType> = records.iter().map(|r| &r[index]).collect();
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                Ok(DataType::String(values.join(", ").into()))
+            }
+            // a column outside of an aggregation has a value
+            // only if all the records agree on it
+            Expression::Variable(col) => {
+                let index = Self::col_index(columns, col)?;
+                let values: BTreeSet<&DataType> = records.iter().map(|r| &r[index]).collect();
+                match values.len() {
+                    1 => Ok(values.into_iter().next().unwrap().clone()),
+                    _ => Err(EvalError {
+                        message: format!("{} has {} possible values", col, values.len()),
+                    }),
+                }
+            }
+            Expression::Class(_, _, _) | Expression::Histogram(_, _) => Err(self.dimension_error()),
+            Expression::Call(function @ (Function::If | Function::Case), args) => {
+                let condition = |i: usize| args[i].eval(columns, records).map(|v| v.is_true());
+                let branch = function.branch(args.len(), condition)?;
+                branch.map_or(Ok(DataType::Null), |i| args[i].eval(columns, records))
+            }
+            // the function of the aggregated arguments
+            Expression::Call(function, args) => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(arg.eval(columns, records)?);
+                }
+                function
+                    .apply(&values)
+                    .map_err(|e| self.arithmetic_error(e))
+            }
+        }
+    }
+
+    /// evaluates the expression for a single record
+    pub(crate) fn eval_record(
+        &self,
+        columns: &Columns,
+        record: &Record,
+    ) -> Result<DataType, EvalError> {
+        match self {
+            Expression::StrConst(s) => Ok(DataType::String(s.as_str().into())),
+            Expression::IntConst(i) => Ok(DataType::Int(*i)),
+            Expression::DecConst(v, p) => Ok(DataType::Decimal(*v, *p)),
+            Expression::Variable(col) => Ok(record[Self::col_index(columns, col)?].clone()),
+            Expression::Add(a, b) => DataType::sum(
+                a.eval_record(columns, record)?,
+                b.eval_record(columns, record)?,
+            )
+            .map_err(|e| self.arithmetic_error(e)),
+            Expression::Sum(_) | Expression::Count | Expression::Values(_) => Err(EvalError {
+                message: format!("nested aggregation in {}", self),
+            }),
+            Expression::Class(e, w, p) => {
+                let bins = Bins::class(*w, *p);
+                let bucket = bins
+                    .bucket(&e.eval_record(columns, record)?)
+                    .map_err(|e| self.arithmetic_error(e))?;
+                Ok(bucket.map_or(DataType::Null, |lower| bins.label(lower)))
+            }
+            // the buckets depend on all the records
+            Expression::Histogram(_, _) => Err(self.dimension_error()),
+            Expression::Call(function @ (Function::If | Function::Case), args) => {
+                let condition =
+                    |i: usize| args[i].eval_record(columns, record).map(|v| v.is_true());
+                let branch = function.branch(args.len(), condition)?;
+                branch.map_or(Ok(DataType::Null), |i| args[i].eval_record(columns, record))
+            }
+            Expression::Call(function, args) => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(arg.eval_record(columns, record)?);
+                }
+                function
+                    .apply(&values)
+                    .map_err(|e| self.arithmetic_error(e))
+            }
+        }
+    }
+
+    /// the error for a bucket expression used outside of a group by dimension
+    pub(crate) fn dimension_error(&self) -> EvalError {
+        EvalError {
+            message: format!("{} can only be a group by dimension", self),
+        }
+    }
+
+    /// an overflow or division error, with the expression it happened in
+    pub(crate) fn arithmetic_error(&self, e: ArithmeticError) -> EvalError {
+        EvalError {
+            message: format!("{}: {}", self, e),
+        }
+    }
+
+    pub(crate) fn col_index(columns: &Columns, col: &str) -> Result<usize, EvalError> {
+        columns.iter().position(|c| c == col).ok_or_else(|| EvalError {
+            message: format!("no column named {}", col),
+        })
+    }
+}
+
+/// a row of a grouped result, the values of the
+/// dimensions and the value of each expression
+pub type GroupRow = (Vec<DataType>, Vec<DataType>);
+
+/// what group by results are ordered by
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// the values of the dimensions
+    #[default]
+    Value,
+    /// the number of records in the group
+    Frequency,
+    /// an aggregation evaluated over the group
+    Expression(Expression),
+}
+
+/// the order of group by results and which of them to keep,
+/// ties are ordered by the values of the dimensions
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    /// how strings are ordered, among the values and the sort keys
+    pub collation: Collation,
+}
+
+impl Sort {
+    /// parses `<key> [asc|desc]`, the key is `value`, `count`
+    /// or an aggregation such as `sum(age)`
+    pub fn from_string<'a>(s: &str) -> Result<Sort, ParseExpressionError<'a>> {
+        let s = s.trim();
+        let (key, descending) = match s.rsplit_once(' ') {
+            Some((key, "desc")) => (key, true),
+            Some((key, "asc")) => (key, false),
+            _ => (s, false),
+        };
+        let key = match key.trim() {
+            "value" => SortKey::Value,
+            "count" => SortKey::Frequency,
+            key => SortKey::Expression(Expression::from_string(key)?),
+        };
+        Ok(Sort {
+            key,
+            descending,
+            ..Sort::default()
+        })
+    }
+}
+
+/// orders items by a comparison function, ranking them in a heap
+struct Ranked<'c, T> {
+    item: T,
+    compare: &'c dyn Fn(&T, &T) -> Ordering,
+}
+
+impl<T> PartialEq for Ranked<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
 }
 
+impl<T> Eq for Ranked<'_, T> {}
+
+impl<T> PartialOrd for Ranked<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Ranked<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&self.item, &other.item)
+    }
+}
+
+/// the items from `offset` up to `offset + limit` in the order
+/// given by `compare`, with a limit only that many items are
+/// kept in a heap instead of sorting all of them
+pub fn top_n<T>(
+    items: impl IntoIterator<Item = T>,
+    compare: impl Fn(&T, &T) -> Ordering,
+    offset: usize,
+    limit: Option<usize>,
+) -> Vec<T> {
+    let n = match limit {
+        Some(limit) => offset.saturating_add(limit),
+        None => {
+            let mut items: Vec<T> = items.into_iter().collect();
+            items.sort_by(&compare);
+            return items.into_iter().skip(offset).collect();
+        }
+    };
+    if n == 0 {
+        return vec![];
+    }
+
+    // a max heap of the best n so far, the worst of them on top
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for item in items {
+        if heap.len() == n {
+            let worst: &Ranked<T> = heap.peek().expect("heap is full");
+            if compare(&item, &worst.item) != Ordering::Less {
+                continue;
+            }
+            heap.pop();
+        }
+        heap.push(Ranked {
+            item,
+            compare: &compare,
+        });
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .skip(offset)
+        .map(|r| r.item)
+        .collect()
+}
+
+/// orders two values for comparisons, numbers by value so that 5 and
+/// 5.0 are equal, dates and timestamps by time, also when the other
+/// is the text of a date
+fn compare_values(a: &DataType, b: &DataType) -> Ordering {
+    let time = |v: &DataType| match v {
+        DataType::String(s) => DataType::parse_date(s).and_then(|d| d.as_seconds()),
+        _ => v.as_seconds(),
+    };
+    if let (Some((a, pa)), Some((b, pb))) = (as_number(a), as_number(b)) {
+        return decimal::compare(a, pa, b, pb);
+    }
+    match (a.as_seconds().or(b.as_seconds()), time(a), time(b)) {
+        (Some(_), Some(a), Some(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// the number and scale of a value, none for values that are not numbers
+fn as_number(value: &DataType) -> Option<(i128, u8)> {
+    match value {
+        DataType::Int(n) => Some((*n as i128, 0)),
+        DataType::Decimal(n, p) => Some((*n as i128, *p)),
+        _ => None,
+    }
+}
+
+/// equal width buckets of numbers, bounds are scaled integers
+/// at the scale of the buckets so they are exact for decimals
+#[derive(Debug)]
+struct Bins {
+    start: i128,
+    width: i128,
+    scale: u8,
+    /// the lower bound of the last bucket, larger numbers fall in it
+    last: Option<i128>,
+}
+
+impl Bins {
+    /// buckets starting at multiples of the width
+    fn class(width: i64, scale: u8) -> Bins {
+        Bins {
+            start: 0,
+            width: width as i128,
+            scale,
+            last: None,
+        }
+    }
+
+    /// `count` buckets from the smallest to the largest of the numbers,
+    /// at the largest scale among them
+    fn histogram(values: &[DataType], count: i64) -> Result<Bins, ArithmeticError> {
+        let overflow = || decimal::overflow("histogram");
+        let numbers: Vec<(i128, u8)> = values.iter().filter_map(as_number).collect();
+        let scale = numbers.iter().map(|(_, p)| *p).max().unwrap_or(0);
+        let scaled = numbers
+            .iter()
+            .map(|(v, p)| decimal::scale_up(*v, scale - p))
+            .collect::<Option<Vec<i128>>>()
+            .ok_or_else(overflow)?;
+        let min = scaled.iter().min().copied().unwrap_or(0);
+        let max = scaled.iter().max().copied().unwrap_or(0);
+
+        let range = max.checked_sub(min).ok_or_else(overflow)?;
+        let width = decimal::div_round(range, count as i128, Rounding::Ceiling)
+            .ok_or_else(overflow)?
+            .max(1);
+        let last = width
+            .checked_mul(count as i128 - 1)
+            .and_then(|l| l.checked_add(min))
+            .ok_or_else(overflow)?;
+        Ok(Bins {
+            start: min,
+            width,
+            scale,
+            last: Some(last),
+        })
+    }
+
+    /// the lower bound of the bucket a value falls in,
+    /// none for values that are not numbers
+    fn bucket(&self, value: &DataType) -> Result<Option<i128>, ArithmeticError> {
+        let overflow = || decimal::overflow("bucket");
+        let (v, p) = match as_number(value) {
+            Some(number) => number,
+            None => return Ok(None),
+        };
+        let scale = p.max(self.scale);
+        let up = |n: i128, from: u8| decimal::scale_up(n, scale - from).ok_or_else(overflow);
+        let offset = up(v, p)?
+            .checked_sub(up(self.start, self.scale)?)
+            .ok_or_else(overflow)?;
+        let n = decimal::div_round(offset, up(self.width, self.scale)?, Rounding::Floor)
+            .ok_or_else(overflow)?;
+        let lower = n
+            .checked_mul(self.width)
+            .and_then(|l| l.checked_add(self.start))
+            .ok_or_else(overflow)?;
+        Ok(Some(match self.last {
+            Some(last) => lower.clamp(self.start, last),
+            None => lower,
+        }))
+    }
+
+    /// the label of the bucket with a lower bound, like `30 <= x < 40`,
+    /// the last bucket includes its upper bound
+    fn label(&self, lower: i128) -> DataType {
+        let upper = lower.saturating_add(self.width);
+        let op = if Some(lower) == self.last { "<=" } else { "<" };
+        let label = format!(
+            "{} <= x {} {}",
+            decimal::format_decimal(lower, self.scale),
+            op,
+            decimal::format_decimal(upper, self.scale)
+        );
+        DataType::String(label.into())
+    }
+}
+
+/// the expression of a group by dimension, `class` and `histogram`
+/// put numbers into buckets, functions such as `date_trunc(month,
+/// date)` are evaluated per record, anything else is a column name
+pub fn dimension_expression(dim: &str) -> Expression {
+    match Expression::from_string(dim) {
+        Ok(
+            e @ (Expression::Class(_, _, _) | Expression::Histogram(_, _) | Expression::Call(_, _)),
+        ) => e,
+        _ => Expression::Variable(String::from(dim)),
+    }
+}
+
+/// a group by dimension, a column, the buckets of an expression
+/// or an expression of each record
+enum Dimension<'e> {
+    Column(usize),
+    Bins {
+        expr: &'e Expression,
+        values: &'e Expression,
+        bins: Bins,
+    },
+    Computed(&'e Expression),
+}
+
+/// the value of a dimension for a group, buckets are kept by
+/// their lower bound so they are in numeric order
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum DimValue<'r> {
+    Value(&'r DataType),
+    Bucket(Option<i128>),
+    Computed(DataType),
+}
+
+impl DimValue<'_> {
+    /// compares like `Ord`, with strings ordered by the collation
+    fn collate(&self, other: &DimValue, collation: Collation) -> Ordering {
+        match (self, other) {
+            (DimValue::Value(a), DimValue::Value(b)) => a.collate(b, collation),
+            (DimValue::Computed(a), DimValue::Computed(b)) => a.collate(b, collation),
+            _ => self.cmp(other),
+        }
+    }
+}
+
+/// compares the dimension values of two groups in order
+fn collate_keys(a: &[DimValue], b: &[DimValue], collation: Collation) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a.collate(b, collation))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len()))
+}
+
+/// groups records by the values of the dimensions and evaluates
+/// the expressions for each group kept by the sort, in the order
+/// of the sort
+///
+/// a dimension is a column, `class(<expr>, <width>)` or
+/// `histogram(<expr>, <count>)` to group numbers into buckets,
+/// the buckets of a histogram span the numbers of the records,
+/// or a function of each record such as `year(date)`
+pub fn group_by(
+    columns: &Columns,
+    records: &[&Record],
+    dims: &[Column],
+    exprs: &[Expression],
+    sort: &Sort,
+) -> Result<Vec<GroupRow>, EvalError> {
+    let dim_exprs: Vec<Expression> = dims.iter().map(|d| dimension_expression(d)).collect();
+    let mut dimensions = vec![];
+    for expr in &dim_exprs {
+        dimensions.push(match expr {
+            Expression::Class(values, w, p) => Dimension::Bins {
+                expr,
+                values,
+                bins: Bins::class(*w, *p),
+            },
+            Expression::Histogram(values, n) => {
+                let mut numbers = vec![];
+                for record in records {
+                    numbers.push(values.eval_record(columns, record)?);
+                }
+                Dimension::Bins {
+                    expr,
+                    values,
+                    bins: Bins::histogram(&numbers, *n).map_err(|e| expr.arithmetic_error(e))?,
+                }
+            }
+            Expression::Variable(col) => Dimension::Column(Expression::col_index(columns, col)?),
+            _ => Dimension::Computed(expr),
+        });
+    }
+
+    let mut groups: BTreeMap<Vec<DimValue>, Vec<&Record>> = BTreeMap::new();
+    for record in records {
+        let mut key = vec![];
+        for dim in &dimensions {
+            key.push(match dim {
+                Dimension::Column(i) => DimValue::Value(&record[*i]),
+                Dimension::Bins { expr, values, bins } => DimValue::Bucket(
+                    bins.bucket(&values.eval_record(columns, record)?)
+                        .map_err(|e| expr.arithmetic_error(e))?,
+                ),
+                Dimension::Computed(expr) => DimValue::Computed(expr.eval_record(columns, record)?),
+            });
+        }
+        groups.entry(key).or_default().push(record);
+    }
+    // without dimensions there is always exactly one group
+    if dims.is_empty() && groups.is_empty() {
+        groups.insert(vec![], vec![]);
+    }
+
+    let mut ranked = vec![];
+    for (key, group) in groups {
+        let sort_value = match &sort.key {
+            SortKey::Value => DataType::Null,
+            SortKey::Frequency => DataType::Int(group.len() as i64),
+            SortKey::Expression(e) => e.eval(columns, &group)?,
+        };
+        ranked.push((sort_value, key, group));
+    }
+    let direction = |o: Ordering| if sort.descending { o.reverse() } else { o };
+    let top = top_n(
+        ranked,
+        |a, b| {
+            let keys = || collate_keys(&a.1, &b.1, sort.collation);
+            direction(a.0.collate(&b.0, sort.collation)).then_with(|| match sort.key {
+                SortKey::Value => direction(keys()),
+                _ => keys(),
+            })
+        },
+        sort.offset,
+        sort.limit,
+    );
+
+    let mut rows = vec![];
+    for (_, key, group) in top {
+        let mut values = vec![];
+        for expr in exprs {
+            values.push(expr.eval(columns, &group)?);
+        }
+        let key = key
+            .into_iter()
+            .zip(&dimensions)
+            .map(|(value, dim)| match (value, dim) {
+                (DimValue::Value(v), _) => v.clone(),
+                (DimValue::Computed(v), _) => v,
+                (DimValue::Bucket(Some(lower)), Dimension::Bins { bins, .. }) => bins.label(lower),
+                (DimValue::Bucket(_), _) => DataType::Null,
+            })
+            .collect();
+        rows.push((key, values));
+    }
+    Ok(rows)
+}
+
 pub type ColReference = String;
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub enum Expression {
-    // StrConst(String),
+    StrConst(String),
     IntConst(i64),
     DecConst(i64, u8),
     Variable(ColReference),
@@ -375,6 +1517,288 @@ pub enum Expression {
     Sum(Box<Expression>),
     Count,
     Values(ColReference),
+    /// numbers in buckets of a width, starting at multiples of it
+    Class(Box<Expression>, i64, u8),
+    /// numbers in a number of equal width buckets, from the
+    /// smallest to the largest of them
+    Histogram(Box<Expression>, i64),
+    Call(Function, Vec<Expression>),
+}
+
+/// a function of the values of its arguments, the date functions
+/// take the dates of timestamps too and give null for other values
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Function {
+    Year,
+    Quarter,
+    Month,
+    /// the ISO 8601 week
+    Week,
+    Day,
+    /// 1 for monday to 7 for sunday
+    Weekday,
+    /// the start of the unit a date is in
+    DateTrunc(Unit),
+    /// `date_add(unit, n, date)`
+    DateAdd(Unit),
+    /// `date_diff(unit, from, to)`, the number of unit boundaries between them
+    DateDiff(Unit),
+    Today,
+    /// `date_format(date, 'pattern')`, see `date::format` for the pattern,
+    /// the quotes may be left out when it has no `,`, `)` or `+`
+    DateFormat(String),
+    /// `if(condition, then, else)`, see `DataType::is_true` for conditions
+    If,
+    /// `case when <condition> then <value> ... [else <value>] end`, the
+    /// value of the first true condition, null if none is and there is no else
+    Case,
+    /// the first argument that is not null or empty
+    Coalesce,
+    /// `nullif(a, b)`, null when a equals b, a otherwise
+    NullIf,
+    IsNull,
+    IsNum,
+    /// strings that are not empty, numbers and dates are not text
+    IsText,
+    /// `eq(a, b)`, `lt(a, b)` and the other comparisons, null when
+    /// either value is null
+    Compare(Comparison),
+}
+
+/// how two values are compared, see `compare_values`
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds(&self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Eq => ordering.is_eq(),
+            Comparison::Ne => ordering.is_ne(),
+            Comparison::Lt => ordering.is_lt(),
+            Comparison::Le => ordering.is_le(),
+            Comparison::Gt => ordering.is_gt(),
+            Comparison::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+impl Function {
+    fn name(&self) -> &'static str {
+        match self {
+            Function::Year => "year",
+            Function::Quarter => "quarter",
+            Function::Month => "month",
+            Function::Week => "week",
+            Function::Day => "day",
+            Function::Weekday => "weekday",
+            Function::DateTrunc(_) => "date_trunc",
+            Function::DateAdd(_) => "date_add",
+            Function::DateDiff(_) => "date_diff",
+            Function::Today => "today",
+            Function::DateFormat(_) => "date_format",
+            Function::If => "if",
+            Function::Case => "case",
+            Function::Coalesce => "coalesce",
+            Function::NullIf => "nullif",
+            Function::IsNull => "isnull",
+            Function::IsNum => "isnum",
+            Function::IsText => "istext",
+            Function::Compare(Comparison::Eq) => "eq",
+            Function::Compare(Comparison::Ne) => "ne",
+            Function::Compare(Comparison::Lt) => "lt",
+            Function::Compare(Comparison::Le) => "le",
+            Function::Compare(Comparison::Gt) => "gt",
+            Function::Compare(Comparison::Ge) => "ge",
+        }
+    }
+
+    /// a call of the function with a name, units and patterns
+    /// are taken from the arguments
+    fn call<'a>(
+        name: &str,
+        mut args: Vec<Expression>,
+    ) -> Result<Expression, ParseExpressionError<'a>> {
+        let word = |arg: Expression| match arg {
+            Expression::Variable(word) => Some(word),
+            _ => None,
+        };
+        let unit = |args: &mut Vec<Expression>| {
+            if args.is_empty() {
+                return None;
+            }
+            word(args.remove(0)).and_then(|w| Unit::from_string(&w))
+        };
+        let unit_error = ParseExpressionError {
+            message: "Expected a date unit",
+        };
+
+        let (function, arity) = match name {
+            "year" => (Function::Year, 1),
+            "quarter" => (Function::Quarter, 1),
+            "month" => (Function::Month, 1),
+            "week" => (Function::Week, 1),
+            "day" => (Function::Day, 1),
+            "weekday" => (Function::Weekday, 1),
+            "date_trunc" => (Function::DateTrunc(unit(&mut args).ok_or(unit_error)?), 1),
+            "date_add" => (Function::DateAdd(unit(&mut args).ok_or(unit_error)?), 2),
+            "date_diff" => (Function::DateDiff(unit(&mut args).ok_or(unit_error)?), 2),
+            "today" => (Function::Today, 0),
+            "date_format" => {
+                let pattern = match args.len() {
+                    2 => match args.pop().expect("two arguments") {
+                        Expression::StrConst(s) => Some(s),
+                        arg => word(arg),
+                    },
+                    _ => None,
+                };
+                let pattern = pattern.ok_or(ParseExpressionError {
+                    message: "Expected a date format",
+                })?;
+                (Function::DateFormat(pattern), 1)
+            }
+            "if" => (Function::If, 3),
+            // any number of arguments, at least one
+            "coalesce" => (Function::Coalesce, args.len().max(1)),
+            "nullif" => (Function::NullIf, 2),
+            "isnull" => (Function::IsNull, 1),
+            "isnum" => (Function::IsNum, 1),
+            "istext" => (Function::IsText, 1),
+            "eq" => (Function::Compare(Comparison::Eq), 2),
+            "ne" => (Function::Compare(Comparison::Ne), 2),
+            "lt" => (Function::Compare(Comparison::Lt), 2),
+            "le" => (Function::Compare(Comparison::Le), 2),
+            "gt" => (Function::Compare(Comparison::Gt), 2),
+            "ge" => (Function::Compare(Comparison::Ge), 2),
+            _ => {
+                return Err(ParseExpressionError {
+                    message: "Unknown function",
+                })
+            }
+        };
+        if args.len() != arity {
+            return Err(ParseExpressionError {
+                message: "Wrong number of arguments",
+            });
+        }
+        Ok(Expression::Call(function, args))
+    }
+
+    /// the index of the argument that is the value of an `if`, or
+    /// otherwise of a `case`, given the number of arguments, none when
+    /// no condition is true and there is no else
+    ///
+    /// only the conditions up to the first true one are evaluated, and
+    /// only the branch taken needs to be, so that an error such as an
+    /// overflow in another branch doesn't fail the expression
+    pub(crate) fn branch<E>(
+        &self,
+        len: usize,
+        mut condition: impl FnMut(usize) -> Result<bool, E>,
+    ) -> Result<Option<usize>, E> {
+        if *self == Function::If {
+            return Ok(Some(if condition(0)? { 1 } else { 2 }));
+        }
+        let mut i = 0;
+        while i + 1 < len {
+            if condition(i)? {
+                return Ok(Some(i + 1));
+            }
+            i += 2;
+        }
+        Ok(if i < len { Some(i) } else { None })
+    }
+
+    /// the value of the function for the values of its arguments
+    pub(crate) fn apply(&self, args: &[DataType]) -> Result<DataType, ArithmeticError> {
+        let seconds = |i: usize| args[i].as_seconds();
+        let days = |i: usize| seconds(i).map(|s| s.div_euclid(date::SECONDS_PER_DAY));
+        let civil = |i: usize| days(i).map(date::civil_from_days);
+        let int = |v: Option<i64>| v.map_or(DataType::Null, DataType::Int);
+        let out_of_range = || ArithmeticError {
+            message: String::from("date out of range"),
+        };
+        // keeps dates dates, unless a time of day is added
+        let like = |value: &DataType, s: i64, unit: Unit| match value {
+            DataType::Date(_) if unit.is_days() => {
+                i32::try_from(s.div_euclid(date::SECONDS_PER_DAY))
+                    .map(DataType::Date)
+                    .map_err(|_| out_of_range())
+            }
+            _ => Ok(DataType::Timestamp(s)),
+        };
+
+        Ok(match self {
+            Function::Year => int(civil(0).map(|(y, _, _)| y)),
+            Function::Quarter => int(civil(0).map(|(_, m, _)| date::quarter(m) as i64)),
+            Function::Month => int(civil(0).map(|(_, m, _)| m as i64)),
+            Function::Week => int(days(0).map(|d| date::iso_week(d).1 as i64)),
+            Function::Day => int(civil(0).map(|(_, _, d)| d as i64)),
+            Function::Weekday => int(days(0).map(|d| date::weekday(d) as i64)),
+            Function::DateTrunc(unit) => match seconds(0) {
+                Some(s) => {
+                    let s = date::truncate(s, *unit).ok_or_else(out_of_range)?;
+                    like(&args[0], s, *unit)?
+                }
+                None => DataType::Null,
+            },
+            Function::DateAdd(unit) => match (&args[0], seconds(1)) {
+                (DataType::Int(n), Some(s)) => {
+                    let s = date::add(s, *n, *unit).ok_or_else(out_of_range)?;
+                    like(&args[1], s, *unit)?
+                }
+                _ => DataType::Null,
+            },
+            Function::DateDiff(unit) => match (seconds(0), seconds(1)) {
+                (Some(from), Some(to)) => {
+                    DataType::Int(date::diff(from, to, *unit).ok_or_else(out_of_range)?)
+                }
+                _ => DataType::Null,
+            },
+            Function::Today => i32::try_from(date::today())
+                .map(DataType::Date)
+                .map_err(|_| out_of_range())?,
+            Function::DateFormat(pattern) => match seconds(0) {
+                Some(s) => DataType::String(date::format(s, pattern).into()),
+                None => DataType::Null,
+            },
+            Function::If => match args[0].is_true() {
+                true => args[1].clone(),
+                false => args[2].clone(),
+            },
+            Function::Case => {
+                let mut parts = args.chunks_exact(2);
+                match parts.find(|part| part[0].is_true()) {
+                    Some(part) => part[1].clone(),
+                    None => parts.remainder().first().cloned().unwrap_or(DataType::Null),
+                }
+            }
+            Function::Coalesce => args
+                .iter()
+                .find(|v| !v.is_null())
+                .cloned()
+                .unwrap_or(DataType::Null),
+            Function::NullIf => match compare_values(&args[0], &args[1]).is_eq() {
+                true => DataType::Null,
+                false => args[0].clone(),
+            },
+            Function::IsNull => DataType::Bool(args[0].is_null()),
+            Function::IsNum => DataType::Bool(as_number(&args[0]).is_some()),
+            Function::IsText => {
+                DataType::Bool(matches!(&args[0], DataType::String(s) if !s.is_empty()))
+            }
+            Function::Compare(_) if args[0].is_null() || args[1].is_null() => DataType::Null,
+            Function::Compare(comparison) => {
+                DataType::Bool(comparison.holds(compare_values(&args[0], &args[1])))
+            }
+        })
+    }
 }
 
 // fn build() {
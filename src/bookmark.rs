@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::json::JsonValue;
+use crate::model::Selection;
+
+#[derive(Debug)]
+pub struct BookmarkError {
+    pub message: String,
+}
+
+impl std::fmt::Display for BookmarkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// named selection states, persisted as a JSON file
+/// next to the data file they were made on
+pub struct Bookmarks {
+    path: PathBuf,
+    entries: BTreeMap<String, Vec<Selection>>,
+}
+
+impl Bookmarks {
+    /// the bookmark file used for a given data file
+    pub fn path_for(data_file: &str) -> PathBuf {
+        PathBuf::from(format!("{}.bookmarks.json", data_file))
+    }
+
+    /// loads bookmarks from a file, a missing file
+    /// gives an empty set of bookmarks
+    pub fn load(path: &Path) -> Result<Bookmarks, BookmarkError> {
+        let mut bookmarks = Bookmarks {
+            path: path.to_path_buf(),
+            entries: BTreeMap::new(),
+        };
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(bookmarks),
+            Err(e) => {
+                return Err(BookmarkError {
+                    message: format!("failed to read {}: {}", path.display(), e),
+                })
+            }
+        };
+
+        let invalid = |message: &str| BookmarkError {
+            message: format!("invalid bookmark file {}: {}", path.display(), message),
+        };
+
+        let json = JsonValue::from_string(&content).map_err(|e| invalid(&e.to_string()))?;
+        let members = json
+            .as_object()
+            .ok_or_else(|| invalid("expected an object"))?;
+        for (name, selections) in members {
+            let selections = selections
+                .as_array()
+                .ok_or_else(|| invalid("expected a list of selections"))?;
+            let mut state = vec![];
+            for s in selections {
+                let column = s.get("column").and_then(JsonValue::as_str);
+                let value = s.get("value").and_then(JsonValue::as_str);
+                match (column, value) {
+                    (Some(column), Some(value)) => state.push(Selection {
+                        column: String::from(column),
+                        value: String::from(value),
+                    }),
+                    _ => return Err(invalid("expected column and value")),
+                }
+            }
+            bookmarks.entries.insert(String::from(name), state);
+        }
+
+        Ok(bookmarks)
+    }
+
+    /// writes all bookmarks to the bookmark file
+    pub fn save(&self) -> Result<(), BookmarkError> {
+        let json = JsonValue::Object(
+            self.entries
+                .iter()
+                .map(|(name, state)| {
+                    let selections = state
+                        .iter()
+                        .map(|s| {
+                            JsonValue::Object(vec![
+                                (String::from("column"), JsonValue::String(s.column.clone())),
+                                (String::from("value"), JsonValue::String(s.value.clone())),
+                            ])
+                        })
+                        .collect();
+                    (name.clone(), JsonValue::Array(selections))
+                })
+                .collect(),
+        );
+
+        fs::write(&self.path, json.to_string()).map_err(|e| BookmarkError {
+            message: format!("failed to write {}: {}", self.path.display(), e),
+        })
+    }
+
+    /// adds or replaces a bookmark
+    pub fn add(&mut self, name: &str, selection: &[Selection]) {
+        self.entries.insert(String::from(name), selection.to_vec());
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Vec<Selection>> {
+        self.entries.get(name)
+    }
+
+    /// bookmark names in alphabetical order
+    pub fn names(&self) -> Vec<&String> {
+        self.entries.keys().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn selection(column: &str, value: &str) -> Selection {
+        Selection {
+            column: String::from(column),
+            value: String::from(value),
+        }
+    }
+
+    #[test]
+    fn save_and_load_bookmarks() {
+        let path = std::env::temp_dir().join(format!("bookmarks-{}.json", std::process::id()));
+
+        let mut bookmarks = Bookmarks::load(&path).unwrap();
+        assert!(bookmarks.names().is_empty());
+
+        let state = vec![selection("country", "swe"), selection("country", "cn")];
+        bookmarks.add("nordic", &state);
+        bookmarks.add("empty", &[]);
+        bookmarks.save().unwrap();
+
+        let loaded = Bookmarks::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.names(), vec!["empty", "nordic"]);
+        assert_eq!(loaded.get("nordic"), Some(&state));
+        assert_eq!(loaded.get("empty"), Some(&vec![]));
+    }
+}
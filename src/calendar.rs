@@ -0,0 +1,176 @@
+//! master calendars, tables of the calendar fields of the dates
+//! in a column, associated with the column's table by the dates
+
+use std::collections::BTreeSet;
+
+use crate::date;
+use crate::model::{Column, DataType, Model, Table};
+
+/// the fields of a calendar, after the date column
+pub const FIELDS: [&str; 5] = ["year", "quarter", "month", "week", "weekday"];
+
+/// the columns of a table that hold dates or timestamps,
+/// and nothing else but nulls
+pub fn date_columns(table: &Table) -> Vec<&Column> {
+    table
+        .get_columns()
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| {
+            let values = || table.records.iter().map(|r| &r[*i]);
+            values().any(|v| v.as_seconds().is_some())
+                && values().all(|v| v.as_seconds().is_some() || *v == DataType::Null)
+        })
+        .map(|(_, column)| column)
+        .collect()
+}
+
+/// a calendar for a date column of a table, with a row per distinct
+/// date or timestamp in it, the fields are named with the prefix
+pub fn calendar(table: &Table, column: &str, prefix: &str) -> Table {
+    let mut columns = vec![String::from(column)];
+    columns.extend(FIELDS.iter().map(|f| format!("{}{}", prefix, f)));
+    let mut calendar = Table::new(&format!("calendar_{}", column), columns);
+
+    let i = match table.get_col_index(column) {
+        Some(i) => i,
+        None => return calendar,
+    };
+    let dates: BTreeSet<&DataType> = table.records.iter().map(|r| &r[i]).collect();
+    for value in dates {
+        let days = match value.as_seconds() {
+            Some(seconds) => seconds.div_euclid(date::SECONDS_PER_DAY),
+            None => continue,
+        };
+        let (year, month, _) = date::civil_from_days(days);
+        calendar.insert(vec![
+            value.clone(),
+            DataType::Int(year),
+            DataType::Int(date::quarter(month) as i64),
+            DataType::Int(month as i64),
+            DataType::Int(date::iso_week(days).1 as i64),
+            DataType::Int(date::weekday(days) as i64),
+        ]);
+    }
+    calendar
+}
+
+/// adds a calendar for every date column of a table in the model and
+/// returns their names
+///
+/// the fields are named `year`, `month` and so on, so selecting
+/// `year=2023` selects the records of that year, unless the table has
+/// more than one date column or the names are taken, then they are
+/// prefixed with the column name, like `shipped.year`
+pub fn add_calendars(model: &mut Model, table: &str) -> Vec<String> {
+    let table = match model.get_table(table) {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let columns = date_columns(table);
+    let taken = model.get_columns();
+    let plain = columns.len() == 1 && FIELDS.iter().all(|f| !taken.iter().any(|c| c == f));
+
+    let calendars: Vec<Table> = columns
+        .iter()
+        .map(|column| {
+            let prefix = match plain {
+                true => String::new(),
+                false => format!("{}.", column),
+            };
+            calendar(table, column, &prefix)
+        })
+        .collect();
+
+    let names = calendars.iter().map(|c| c.name.clone()).collect();
+    for calendar in calendars {
+        model.add_table(calendar);
+    }
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::Selection;
+
+    fn orders() -> Table {
+        let columns = vec![
+            String::from("ordered"),
+            String::from("shipped"),
+            String::from("amount"),
+        ];
+        let mut t = Table::new("orders", columns);
+        for (ordered, shipped, amount) in [
+            ("2022-12-30", "2023-01-02 10:00", "5"),
+            ("2023-01-02", "", "7"),
+            ("2023-01-02", "2023-01-03 08:30", "1"),
+        ] {
+            t.insert(vec![
+                DataType::from_string(ordered),
+                DataType::parse_date(shipped).unwrap_or(DataType::Null),
+                DataType::from_string(amount),
+            ]);
+        }
+        t
+    }
+
+    #[test]
+    fn calendar_fields() {
+        let t = orders();
+        assert_eq!(date_columns(&t), vec!["ordered", "shipped"]);
+
+        let c = calendar(&t, "ordered", "");
+        assert_eq!(c.columns[1..], FIELDS.map(String::from));
+        assert_eq!(c.records.len(), 2);
+        assert_eq!(
+            c.records[0],
+            vec![
+                DataType::from_string("2022-12-30"),
+                DataType::Int(2022),
+                DataType::Int(4),
+                DataType::Int(12),
+                DataType::Int(52),
+                DataType::Int(5),
+            ]
+        );
+    }
+
+    #[test]
+    fn selecting_a_year_selects_records() {
+        let columns = vec![String::from("ordered"), String::from("amount")];
+        let mut t = Table::new("orders", columns);
+        for mut record in orders().records {
+            record.remove(1);
+            t.insert(record);
+        }
+        let mut model = Model::new();
+        model.add_table(t);
+        assert_eq!(
+            add_calendars(&mut model, "orders"),
+            vec!["calendar_ordered"]
+        );
+
+        let mut ctx = model.new_data_context();
+        ctx.select(&Selection {
+            column: String::from("year"),
+            value: String::from("2023"),
+        });
+        let table = model.get_table("orders").unwrap();
+        let amounts: Vec<String> = ctx
+            .get_possible_records(table)
+            .iter()
+            .map(|r| r[1].to_string())
+            .collect();
+        assert_eq!(amounts, vec!["7", "1"]);
+
+        // with two date columns the fields are named by column
+        let mut model = Model::new();
+        model.add_table(orders());
+        let names = add_calendars(&mut model, "orders");
+        assert_eq!(names, vec!["calendar_ordered", "calendar_shipped"]);
+        let shipped = model.get_table("calendar_shipped").unwrap();
+        assert_eq!(shipped.columns[1], "shipped.year");
+        assert_eq!(shipped.records.len(), 2);
+    }
+}
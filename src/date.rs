@@ -0,0 +1,406 @@
+//! calendar dates and timestamps, kept as days and seconds since
+//! 1970-01-01 in the proleptic gregorian calendar, without time zones
+//!
+//! days are converted to and from dates with Howard Hinnant's civil
+//! calendar algorithms, weeks are ISO 8601 weeks starting on monday
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// the largest year dates are computed for, far past the range of
+/// timestamps but small enough for `days_from_civil` not to overflow
+const MAX_YEAR: i64 = 1_000_000_000_000;
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+pub const UNITS: [&str; 8] = [
+    "year", "quarter", "month", "week", "day", "hour", "minute", "second",
+];
+
+/// what dates are truncated to, added in and counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Year,
+    Quarter,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl Unit {
+    pub fn from_string(s: &str) -> Option<Unit> {
+        match s {
+            "year" => Some(Unit::Year),
+            "quarter" => Some(Unit::Quarter),
+            "month" => Some(Unit::Month),
+            "week" => Some(Unit::Week),
+            "day" => Some(Unit::Day),
+            "hour" => Some(Unit::Hour),
+            "minute" => Some(Unit::Minute),
+            "second" => Some(Unit::Second),
+            _ => None,
+        }
+    }
+
+    /// whether the unit is whole days, so that it keeps dates dates
+    pub fn is_days(&self) -> bool {
+        !matches!(self, Unit::Hour | Unit::Minute | Unit::Second)
+    }
+
+    fn seconds(&self) -> i64 {
+        match self {
+            Unit::Hour => 3600,
+            Unit::Minute => 60,
+            _ => 1,
+        }
+    }
+}
+
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let i = match self {
+            Unit::Year => 0,
+            Unit::Quarter => 1,
+            Unit::Month => 2,
+            Unit::Week => 3,
+            Unit::Day => 4,
+            Unit::Hour => 5,
+            Unit::Minute => 6,
+            Unit::Second => 7,
+        };
+        UNITS[i].fmt(f)
+    }
+}
+
+/// days since 1970-01-01 of a date
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (m, d) = (month as i64, day as i64);
+    let y = if m <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// the year, month and day of days since 1970-01-01
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// the day of the week, 1 for monday to 7 for sunday
+pub fn weekday(days: i64) -> u32 {
+    // 1970-01-01 was a thursday
+    ((days + 3).rem_euclid(7) + 1) as u32
+}
+
+/// the quarter of a month, 1 to 4
+pub fn quarter(month: u32) -> u32 {
+    (month - 1) / 3 + 1
+}
+
+/// the ISO 8601 year and week of a day, the week of a year is the
+/// one with its first thursday
+pub fn iso_week(days: i64) -> (i64, u32) {
+    let thursday = days - weekday(days) as i64 + 4;
+    let (year, _, _) = civil_from_days(thursday);
+    let week = (thursday - days_from_civil(year, 1, 1)) / 7 + 1;
+    (year, week as u32)
+}
+
+fn digits(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// parses a `YYYY-MM-DD` date into days since 1970-01-01
+pub fn parse_date(s: &str) -> Option<i64> {
+    let b = s.as_bytes();
+    if b.len() != 10 || b[4] != b'-' || b[7] != b'-' {
+        return None;
+    }
+    let (year, month, day) = (digits(&s[..4])?, digits(&s[5..7])?, digits(&s[8..])?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year as i64, month) {
+        return None;
+    }
+    Some(days_from_civil(year as i64, month, day))
+}
+
+/// parses a `YYYY-MM-DD HH:MM[:SS]` timestamp, with a `T` instead of
+/// the space and an optional `Z`, into seconds since 1970-01-01
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    if s.len() < 16 || !matches!(s.as_bytes()[10], b' ' | b'T') {
+        return None;
+    }
+    let days = parse_date(s.get(..10)?)?;
+    let mut parts = s.get(11..)?.split(':');
+    let mut part = |max: u32| {
+        let p = parts.next()?;
+        digits(p).filter(|v| p.len() == 2 && *v <= max)
+    };
+    let (hour, minute) = (part(23)?, part(59)?);
+    let second = match s.len() {
+        16 => 0,
+        19 => part(59)?,
+        _ => return None,
+    };
+    Some(days * SECONDS_PER_DAY + (hour * 3600 + minute * 60 + second) as i64)
+}
+
+/// formats with a pattern of `strftime` like specifiers, `%Y` year,
+/// `%y` two digit year, `%m` month, `%B` and `%b` month name, `%d`
+/// day, `%j` day of the year, `%A` and `%a` weekday name, `%u`
+/// weekday number, `%V` and `%G` ISO week and year, `%q` quarter,
+/// `%H`, `%M` and `%S` time, anything else is kept as is
+pub fn format(seconds: i64, pattern: &str) -> String {
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let spec = match chars.next() {
+            Some(spec) => spec,
+            None => {
+                out.push('%');
+                break;
+            }
+        };
+        let formatted = match spec {
+            'Y' => format!("{:04}", year),
+            'y' => format!("{:02}", year.rem_euclid(100)),
+            'm' => format!("{:02}", month),
+            'B' => String::from(MONTHS[month as usize - 1]),
+            'b' => String::from(&MONTHS[month as usize - 1][..3]),
+            'd' => format!("{:02}", day),
+            'j' => format!("{:03}", days - days_from_civil(year, 1, 1) + 1),
+            'A' => String::from(WEEKDAYS[weekday(days) as usize - 1]),
+            'a' => String::from(&WEEKDAYS[weekday(days) as usize - 1][..3]),
+            'u' => weekday(days).to_string(),
+            'V' => format!("{:02}", iso_week(days).1),
+            'G' => format!("{:04}", iso_week(days).0),
+            'q' => quarter(month).to_string(),
+            'H' => format!("{:02}", time / 3600),
+            'M' => format!("{:02}", time / 60 % 60),
+            'S' => format!("{:02}", time % 60),
+            '%' => String::from("%"),
+            other => format!("%{}", other),
+        };
+        out.push_str(&formatted);
+    }
+    out
+}
+
+pub fn format_date(days: i64) -> String {
+    format(days * SECONDS_PER_DAY, "%Y-%m-%d")
+}
+
+pub fn format_timestamp(seconds: i64) -> String {
+    format(seconds, "%Y-%m-%d %H:%M:%S")
+}
+
+/// the start of the unit a timestamp is in, none when that is out of range
+pub fn truncate(seconds: i64, unit: Unit) -> Option<i64> {
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let (year, month, _) = civil_from_days(days);
+    let start = match unit {
+        Unit::Year => days_from_civil(year, 1, 1),
+        Unit::Quarter => days_from_civil(year, (quarter(month) - 1) * 3 + 1, 1),
+        Unit::Month => days_from_civil(year, month, 1),
+        Unit::Week => days - weekday(days) as i64 + 1,
+        Unit::Day => days,
+        _ => return Some(seconds - seconds.rem_euclid(unit.seconds())),
+    };
+    start.checked_mul(SECONDS_PER_DAY)
+}
+
+/// adds months, a day past the end of the resulting month
+/// becomes its last day
+fn add_months(seconds: i64, months: i64) -> Option<i64> {
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    let total = (year.checked_mul(12)?)
+        .checked_add(month as i64 - 1)?
+        .checked_add(months)?;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+    if year.abs() > MAX_YEAR {
+        return None;
+    }
+    let day = day.min(days_in_month(year, month));
+    days_from_civil(year, month, day)
+        .checked_mul(SECONDS_PER_DAY)?
+        .checked_add(seconds.rem_euclid(SECONDS_PER_DAY))
+}
+
+/// adds a number of units to a timestamp, none when the result is out of range
+pub fn add(seconds: i64, n: i64, unit: Unit) -> Option<i64> {
+    let step = match unit {
+        Unit::Year => return add_months(seconds, n.checked_mul(12)?),
+        Unit::Quarter => return add_months(seconds, n.checked_mul(3)?),
+        Unit::Month => return add_months(seconds, n),
+        Unit::Week => 7 * SECONDS_PER_DAY,
+        Unit::Day => SECONDS_PER_DAY,
+        _ => unit.seconds(),
+    };
+    seconds.checked_add(n.checked_mul(step)?)
+}
+
+/// the number of unit boundaries from one timestamp to another,
+/// negative when the second is earlier, none when it is out of range
+pub fn diff(from: i64, to: i64, unit: Unit) -> Option<i64> {
+    let months = |seconds: i64| {
+        let (year, month, _) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+        year * 12 + month as i64 - 1
+    };
+    let monday = |seconds: i64| {
+        let days = seconds.div_euclid(SECONDS_PER_DAY);
+        days - weekday(days) as i64 + 1
+    };
+    match unit {
+        Unit::Year => Some(months(to).div_euclid(12) - months(from).div_euclid(12)),
+        Unit::Quarter => Some(months(to).div_euclid(3) - months(from).div_euclid(3)),
+        Unit::Month => Some(months(to) - months(from)),
+        Unit::Week => Some((monday(to) - monday(from)) / 7),
+        Unit::Day => Some(to.div_euclid(SECONDS_PER_DAY) - from.div_euclid(SECONDS_PER_DAY)),
+        _ => to
+            .div_euclid(unit.seconds())
+            .checked_sub(from.div_euclid(unit.seconds())),
+    }
+}
+
+/// the current day in UTC
+pub fn today() -> i64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    seconds.div_euclid(SECONDS_PER_DAY)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn civil_calendar() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        for days in [-800_000, -1, 0, 59, 11_016, 19_417, 2_932_896] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+
+        let day = parse_date("2023-01-01").unwrap();
+        assert_eq!(weekday(day), 7);
+        assert_eq!(iso_week(day), (2022, 52));
+        assert_eq!(iso_week(parse_date("2026-12-31").unwrap()), (2026, 53));
+        assert_eq!(iso_week(parse_date("2024-12-30").unwrap()), (2025, 1));
+    }
+
+    #[test]
+    fn parse_and_format() {
+        assert_eq!(
+            parse_date("2024-02-29").map(format_date).as_deref(),
+            Some("2024-02-29")
+        );
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2023-1-01"), None);
+        assert_eq!(parse_date("2023-13-01"), None);
+
+        let t = parse_timestamp("2023-04-05T06:07:08Z").unwrap();
+        assert_eq!(format_timestamp(t), "2023-04-05 06:07:08");
+        assert_eq!(parse_timestamp("2023-04-05 06:07"), Some(t - 8));
+        assert_eq!(parse_timestamp("2023-04-05 24:00"), None);
+        assert_eq!(parse_timestamp("2023-04-05"), None);
+
+        assert_eq!(
+            format(t, "%d.%m.%y %a %b, week %V of %G, Q%q, day %j, 100%%"),
+            "05.04.23 Wed Apr, week 14 of 2023, Q2, day 095, 100%"
+        );
+    }
+
+    #[test]
+    fn truncate_add_and_diff() {
+        let t = parse_timestamp("2023-05-31 13:45:10").unwrap();
+        let at = |unit| format_timestamp(truncate(t, unit).unwrap());
+        assert_eq!(at(Unit::Year), "2023-01-01 00:00:00");
+        assert_eq!(at(Unit::Quarter), "2023-04-01 00:00:00");
+        assert_eq!(at(Unit::Week), "2023-05-29 00:00:00");
+        assert_eq!(at(Unit::Hour), "2023-05-31 13:00:00");
+
+        let plus = |n, unit| format_timestamp(add(t, n, unit).unwrap());
+        assert_eq!(plus(1, Unit::Month), "2023-06-30 13:45:10");
+        assert_eq!(plus(-3, Unit::Month), "2023-02-28 13:45:10");
+        assert_eq!(plus(2, Unit::Week), "2023-06-14 13:45:10");
+        assert_eq!(plus(-14, Unit::Hour), "2023-05-30 23:45:10");
+        assert_eq!(add(t, i64::MAX, Unit::Day), None);
+        assert_eq!(add(t, 9_000_000_000_000_000_000, Unit::Month), None);
+        assert_eq!(add(t, -9_000_000_000_000_000, Unit::Year), None);
+        assert_eq!(truncate(i64::MIN, Unit::Year), None);
+
+        let to = parse_timestamp("2024-01-01 00:00").unwrap();
+        assert_eq!(diff(t, to, Unit::Year), Some(1));
+        assert_eq!(diff(t, to, Unit::Quarter), Some(3));
+        assert_eq!(diff(t, to, Unit::Month), Some(8));
+        assert_eq!(diff(t, to, Unit::Day), Some(215));
+        assert_eq!(diff(to, t, Unit::Week), Some(-31));
+        assert_eq!(diff(i64::MIN, i64::MAX, Unit::Second), None);
+    }
+}
@@ -0,0 +1,365 @@
+//! arithmetic on scaled integers, a value `v` with scale `s` is
+//! `v / 10^s`, intermediate results are i128 and every step is
+//! checked, so overflow is an error rather than a wrapped value
+
+use std::cmp::Ordering;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ArithmeticError {
+    pub message: String,
+}
+
+impl std::fmt::Display for ArithmeticError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+pub(crate) fn overflow(op: &str) -> ArithmeticError {
+    ArithmeticError {
+        message: format!("decimal overflow in {}", op),
+    }
+}
+
+/// how digits dropped by a division or a smaller scale are rounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// to the nearest, ties away from zero
+    HalfUp,
+    /// to the nearest, ties to the even neighbour
+    HalfEven,
+    /// towards zero
+    Down,
+    /// towards negative infinity
+    Floor,
+    /// towards positive infinity
+    Ceiling,
+}
+
+impl Rounding {
+    pub fn from_string(s: &str) -> Option<Rounding> {
+        match s {
+            "half_up" => Some(Rounding::HalfUp),
+            "half_even" => Some(Rounding::HalfEven),
+            "down" => Some(Rounding::Down),
+            "floor" => Some(Rounding::Floor),
+            "ceiling" => Some(Rounding::Ceiling),
+            _ => None,
+        }
+    }
+}
+
+fn pow10(exp: u32) -> Option<i128> {
+    10i128.checked_pow(exp)
+}
+
+/// the value at a larger scale, none if it does not fit
+pub(crate) fn scale_up(v: i128, by: u8) -> Option<i128> {
+    if v == 0 {
+        return Some(0);
+    }
+    pow10(by as u32)?.checked_mul(v)
+}
+
+/// divides and rounds the quotient, `d` must not be zero
+pub(crate) fn div_round(n: i128, d: i128, rounding: Rounding) -> Option<i128> {
+    let q = n.checked_div(d)?;
+    let r = n % d;
+    if r == 0 {
+        return Some(q);
+    }
+    let negative = (n < 0) != (d < 0);
+    let away = if negative { q - 1 } else { q + 1 };
+    let (r, d) = (r.unsigned_abs(), d.unsigned_abs());
+    let rounded = match rounding {
+        Rounding::Down => q,
+        Rounding::Floor if negative => away,
+        Rounding::Floor => q,
+        Rounding::Ceiling if negative => q,
+        Rounding::Ceiling => away,
+        Rounding::HalfUp | Rounding::HalfEven => match r.cmp(&(d - r)) {
+            Ordering::Less => q,
+            Ordering::Greater => away,
+            Ordering::Equal if rounding == Rounding::HalfEven && q % 2 == 0 => q,
+            Ordering::Equal => away,
+        },
+    };
+    Some(rounded)
+}
+
+/// the value at another scale, rounding when digits are dropped
+pub(crate) fn rescale(v: i128, from: u8, to: u8, rounding: Rounding) -> Option<i128> {
+    if to >= from {
+        scale_up(v, to - from)
+    } else {
+        match pow10((from - to) as u32) {
+            Some(d) => div_round(v, d, rounding),
+            // every digit is dropped
+            None => div_round(v.signum(), i128::MAX, rounding),
+        }
+    }
+}
+
+/// the sum at the larger of the two scales
+pub(crate) fn add(a: i128, pa: u8, b: i128, pb: u8) -> Option<(i128, u8)> {
+    let scale = pa.max(pb);
+    let sum = scale_up(a, scale - pa)?.checked_add(scale_up(b, scale - pb)?)?;
+    Some((sum, scale))
+}
+
+/// the exact product, its scale is the sum of the scales
+pub(crate) fn mul(a: i128, pa: u8, b: i128, pb: u8) -> Option<(i128, u8)> {
+    Some((a.checked_mul(b)?, pa.checked_add(pb)?))
+}
+
+/// the quotient at the given scale, `b` must not be zero
+pub(crate) fn div(a: i128, pa: u8, b: i128, pb: u8, scale: u8, rounding: Rounding) -> Option<i128> {
+    // a / 10^pa / (b / 10^pb) * 10^scale
+    let exp = pb as i32 + scale as i32 - pa as i32;
+    if exp >= 0 {
+        div_round(scale_up(a, exp as u8)?, b, rounding)
+    } else {
+        div_round(a, b.checked_mul(pow10(-exp as u32)?)?, rounding)
+    }
+}
+
+/// how numbers are written in a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal_point: char,
+    /// separates groups of three digits, e.g. `1,234,567`
+    pub thousands: Option<char>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> NumberFormat {
+        NumberFormat {
+            decimal_point: '.',
+            thousands: None,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// a decimal comma and dots between thousands, `1.234,5`
+    pub fn decimal_comma() -> NumberFormat {
+        NumberFormat {
+            decimal_point: ',',
+            thousands: Some('.'),
+        }
+    }
+}
+
+/// reads the digits of a number's integer part, which may be
+/// grouped by thousands separators, into `value`
+fn parse_integer_part(part: &str, thousands: Option<char>, value: &mut i128) -> Option<()> {
+    let groups = match thousands {
+        Some(sep) => part.split(sep).collect(),
+        None => vec![part],
+    };
+    // separators only go between groups of exactly three digits
+    if groups.len() > 1
+        && (groups[0].is_empty() || groups[0].len() > 3 || groups[1..].iter().any(|g| g.len() != 3))
+    {
+        return None;
+    }
+    for group in groups {
+        parse_digits(group, value)?;
+    }
+    Some(())
+}
+
+fn parse_digits(digits: &str, value: &mut i128) -> Option<()> {
+    for c in digits.chars() {
+        let digit = c.to_digit(10)?;
+        *value = value.checked_mul(10)?.checked_add(digit as i128)?;
+    }
+    Some(())
+}
+
+/// parses a numeric literal into a scaled integer and its scale,
+/// e.g. `-1.50` is `(-150, 2)` and `1.5e3` is `(1500, 0)`
+///
+/// accepts a sign, digits on either side of the decimal point and
+/// an exponent, none is returned for anything else, including
+/// `inf`, `NaN` and numbers too large for an i128
+pub fn parse_number(s: &str, format: &NumberFormat) -> Option<(i128, u8)> {
+    let (negative, rest) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (mantissa, exponent) = match rest.find(['e', 'E']) {
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None),
+    };
+    let (int_part, frac_part) = match mantissa.split_once(format.decimal_point) {
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (mantissa, ""),
+    };
+    if int_part.is_empty() && frac_part.is_empty() {
+        return None;
+    }
+
+    let mut value = 0i128;
+    parse_integer_part(int_part, format.thousands, &mut value)?;
+    parse_digits(frac_part, &mut value)?;
+    let mut scale = i32::try_from(frac_part.len()).ok()?;
+
+    if let Some(exponent) = exponent {
+        let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let exponent: i32 = exponent.parse().ok()?;
+        scale = scale.checked_sub(exponent)?;
+    }
+    if scale < 0 {
+        value = scale_up(value, u8::try_from(-scale).ok()?)?;
+        scale = 0;
+    }
+    let value = if negative { -value } else { value };
+    Some((value, u8::try_from(scale).ok()?))
+}
+
+/// whether text is written as a number, even one too large for
+/// `parse_number`
+pub fn is_numeric(s: &str, format: &NumberFormat) -> bool {
+    // with every digit a zero nothing can overflow
+    let zeros: String = s
+        .chars()
+        .map(|c| if c.is_ascii_digit() { '0' } else { c })
+        .collect();
+    parse_number(&zeros, format).is_some()
+}
+
+/// writes a scaled integer with its decimals, e.g. `(-5, 2)` is `-0.05`
+pub fn format_decimal(v: i128, scale: u8) -> String {
+    let sign = if v < 0 { "-" } else { "" };
+    let digits = v.unsigned_abs().to_string();
+    let scale = scale as usize;
+    if scale == 0 {
+        return format!("{}{}", sign, digits);
+    }
+    let digits = if digits.len() <= scale {
+        "0".repeat(scale + 1 - digits.len()) + &digits
+    } else {
+        digits
+    };
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}", sign, int_part, frac_part)
+}
+
+/// compares two scaled values exactly
+pub(crate) fn compare(a: i128, pa: u8, b: i128, pb: u8) -> Ordering {
+    let scale = pa.max(pb);
+    // only the value with the smaller scale is scaled up, if it
+    // no longer fits it is further from zero than the other one
+    match (scale_up(a, scale - pa), scale_up(b, scale - pb)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        (None, _) => a.cmp(&0),
+        (_, None) => 0.cmp(&b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_in_every_mode() {
+        let cases = [
+            (25, [3, 2, 2, 2, 3]),
+            (-25, [-3, -2, -2, -3, -2]),
+            (35, [4, 4, 3, 3, 4]),
+            (-21, [-2, -2, -2, -3, -2]),
+            (27, [3, 3, 2, 2, 3]),
+        ];
+        let modes = [
+            Rounding::HalfUp,
+            Rounding::HalfEven,
+            Rounding::Down,
+            Rounding::Floor,
+            Rounding::Ceiling,
+        ];
+        for (v, expected) in cases {
+            for (mode, e) in modes.iter().zip(expected) {
+                assert_eq!(rescale(v, 1, 0, *mode), Some(e), "{} {:?}", v, mode);
+            }
+        }
+        assert_eq!(rescale(5, 60, 0, Rounding::Ceiling), Some(1));
+        assert_eq!(rescale(5, 60, 0, Rounding::HalfUp), Some(0));
+    }
+
+    #[test]
+    fn checked_operations() {
+        assert_eq!(add(150, 2, 1, 0), Some((250, 2)));
+        assert_eq!(mul(-15, 1, 3, 0), Some((-45, 1)));
+        assert_eq!(div(1, 0, 3, 0, 4, Rounding::HalfUp), Some(3333));
+        assert_eq!(div(2, 0, 3, 0, 2, Rounding::Down), Some(66));
+        assert_eq!(div(100, 2, 25, 1, 0, Rounding::HalfEven), Some(0));
+        assert_eq!(add(i128::MAX, 0, 1, 0), None);
+        assert_eq!(mul(i128::MAX, 0, 2, 0), None);
+
+        assert_eq!(compare(150, 2, 15, 1), Ordering::Equal);
+        assert_eq!(compare(-1, 0, 5, 1), Ordering::Less);
+        assert_eq!(compare(i64::MAX as i128, 0, 1, 100), Ordering::Greater);
+        assert_eq!(compare(-1, 0, 1, 100), Ordering::Less);
+    }
+
+    #[test]
+    fn parse_numeric_literals() {
+        let us = NumberFormat::default();
+        let cases = [
+            ("35", Some((35, 0))),
+            ("-0.5", Some((-5, 1))),
+            ("-.5", Some((-5, 1))),
+            (".5", Some((5, 1))),
+            ("5.", Some((5, 0))),
+            ("+1.50", Some((150, 2))),
+            ("1e5", Some((100000, 0))),
+            ("1.5E-3", Some((15, 4))),
+            ("-2.5e+1", Some((-25, 0))),
+            ("inf", None),
+            ("NaN", None),
+            ("1e", None),
+            (".", None),
+            ("-", None),
+            ("1.2.3", None),
+            ("1,234", None),
+            ("1e999", None),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_number(s, &us), expected, "{}", s);
+        }
+
+        let grouped = NumberFormat {
+            decimal_point: '.',
+            thousands: Some(','),
+        };
+        assert_eq!(parse_number("1,234,567.5", &grouped), Some((12345675, 1)));
+        assert_eq!(parse_number("12,34", &grouped), None);
+        assert_eq!(parse_number(",123", &grouped), None);
+
+        let eu = NumberFormat::decimal_comma();
+        assert_eq!(parse_number("-1.234,50", &eu), Some((-123450, 2)));
+        assert_eq!(parse_number("0,5", &eu), Some((5, 1)));
+        assert_eq!(parse_number("0.5", &eu), None);
+
+        // too large to parse, but still numbers
+        assert!(is_numeric("1e999", &us));
+        assert!(is_numeric("-99999999999999999999999999999999999999999", &us));
+        assert!(!is_numeric("1,234", &us));
+        assert!(!is_numeric("2023-05-31", &us));
+    }
+
+    #[test]
+    fn format_decimals() {
+        assert_eq!(format_decimal(-5, 1), "-0.5");
+        assert_eq!(format_decimal(-15, 2), "-0.15");
+        assert_eq!(format_decimal(-150, 2), "-1.50");
+        assert_eq!(format_decimal(7, 3), "0.007");
+        assert_eq!(format_decimal(70000, 1), "7000.0");
+        assert_eq!(format_decimal(99, 0), "99");
+    }
+}
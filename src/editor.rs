@@ -0,0 +1,514 @@
+use std::fs;
+use std::io;
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const MAX_HISTORY: usize = 1000;
+
+/// provides tab completion candidates for a line being edited
+pub trait Completer {
+    /// returns the position where the completed word starts
+    /// and the candidates that can replace it
+    fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>);
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Tab,
+    Enter,
+    Interrupt,
+    Eof,
+    Unknown,
+}
+
+/// reads one key press from a terminal in raw mode,
+/// returns None at end of input
+pub fn read_key(r: &mut impl Read) -> io::Result<Option<Key>> {
+    let mut byte = [0u8; 1];
+    if r.read(&mut byte)? == 0 {
+        return Ok(None);
+    }
+
+    let key = match byte[0] {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        3 => Key::Interrupt,
+        4 => Key::Eof,
+        1 => Key::Home,
+        5 => Key::End,
+        8 | 127 => Key::Backspace,
+        27 => {
+            let mut seq = [0u8; 2];
+            if r.read(&mut seq[..1])? == 0 || r.read(&mut seq[1..])? == 0 {
+                return Ok(Some(Key::Unknown));
+            }
+            match seq {
+                [b'[', b'A'] | [b'O', b'A'] => Key::Up,
+                [b'[', b'B'] | [b'O', b'B'] => Key::Down,
+                [b'[', b'C'] | [b'O', b'C'] => Key::Right,
+                [b'[', b'D'] | [b'O', b'D'] => Key::Left,
+                [b'[', b'H'] | [b'O', b'H'] => Key::Home,
+                [b'[', b'F'] | [b'O', b'F'] => Key::End,
+                [b'[', b'3'] => {
+                    // delete is sent as ESC [ 3 ~
+                    r.read_exact(&mut byte)?;
+                    Key::Delete
+                }
+                _ => Key::Unknown,
+            }
+        }
+        b if b < 0x20 => Key::Unknown,
+        b if b < 0x80 => Key::Char(b as char),
+        b => {
+            // leading byte of a multi byte utf-8 character
+            let len = if b >= 0xf0 {
+                4
+            } else if b >= 0xe0 {
+                3
+            } else {
+                2
+            };
+            let mut buf = vec![b; len];
+            r.read_exact(&mut buf[1..])?;
+            match std::str::from_utf8(&buf)
+                .ok()
+                .and_then(|s| s.chars().next())
+            {
+                Some(c) => Key::Char(c),
+                None => Key::Unknown,
+            }
+        }
+    };
+
+    Ok(Some(key))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Accept(String),
+    Cancel,
+    Eof,
+    /// the completion was ambiguous, the candidates should be listed
+    ShowCandidates(Vec<String>),
+}
+
+/// editing state of the line currently being read
+pub struct LineState {
+    buffer: Vec<char>,
+    cursor: usize,
+    // position in history while browsing with up/down,
+    // together with the line that was being typed
+    history_index: Option<usize>,
+    draft: Vec<char>,
+}
+
+impl Default for LineState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineState {
+    pub fn new() -> LineState {
+        LineState {
+            buffer: vec![],
+            cursor: 0,
+            history_index: None,
+            draft: vec![],
+        }
+    }
+
+    pub fn line(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    fn set_line(&mut self, line: Vec<char>) {
+        self.cursor = line.len();
+        self.buffer = line;
+    }
+
+    pub fn handle(&mut self, key: Key, history: &[String], completer: &dyn Completer) -> Action {
+        match key {
+            Key::Char(c) => {
+                self.buffer.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Delete => {
+                if self.cursor < self.buffer.len() {
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.buffer.len(),
+            Key::Up => {
+                let index = match self.history_index {
+                    None if history.is_empty() => return Action::Continue,
+                    None => {
+                        self.draft = self.buffer.clone();
+                        history.len() - 1
+                    }
+                    Some(i) => i.saturating_sub(1),
+                };
+                self.history_index = Some(index);
+                self.set_line(history[index].chars().collect());
+            }
+            Key::Down => match self.history_index {
+                None => (),
+                Some(i) if i + 1 < history.len() => {
+                    self.history_index = Some(i + 1);
+                    self.set_line(history[i + 1].chars().collect());
+                }
+                Some(_) => {
+                    self.history_index = None;
+                    let draft = std::mem::take(&mut self.draft);
+                    self.set_line(draft);
+                }
+            },
+            Key::Tab => return self.complete(completer),
+            Key::Enter => return Action::Accept(self.line()),
+            Key::Interrupt => return Action::Cancel,
+            Key::Eof => {
+                if self.buffer.is_empty() {
+                    return Action::Eof;
+                }
+            }
+            Key::Unknown => (),
+        }
+        Action::Continue
+    }
+
+    fn complete(&mut self, completer: &dyn Completer) -> Action {
+        let line = self.line();
+        let byte_pos: usize = self.buffer[..self.cursor]
+            .iter()
+            .map(|c| c.len_utf8())
+            .sum();
+        let (start, candidates) = completer.complete(&line, byte_pos);
+        if candidates.is_empty() {
+            return Action::Continue;
+        }
+
+        let prefix = common_prefix(&candidates);
+        let start = line[..start].chars().count();
+        let typed = self.cursor - start;
+        if prefix.chars().count() > typed || candidates.len() == 1 {
+            let mut completed: Vec<char> = self.buffer[..start].to_vec();
+            completed.extend(prefix.chars());
+            let cursor = completed.len();
+            completed.extend(&self.buffer[self.cursor..]);
+            self.buffer = completed;
+            self.cursor = cursor;
+            return Action::Continue;
+        }
+
+        Action::ShowCandidates(candidates)
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix: Vec<char> = candidates[0].chars().collect();
+    for c in &candidates[1..] {
+        let len = prefix
+            .iter()
+            .zip(c.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(len);
+    }
+    prefix.into_iter().collect()
+}
+
+/// switches the terminal into raw mode for as long as it is alive
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    /// returns None if the terminal settings could not be changed
+    fn enable() -> Option<RawMode> {
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let saved = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        let status = Command::new("stty").args(["raw", "-echo"]).status().ok()?;
+        if !status.success() {
+            return None;
+        }
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.saved).status();
+    }
+}
+
+/// reads lines from the terminal with history and tab completion,
+/// falls back to plain line reading when stdin is not a terminal
+/// or it can't be switched to raw mode
+pub struct LineEditor {
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+impl LineEditor {
+    /// creates an editor, loading previous history from the given file
+    pub fn new(history_path: Option<PathBuf>) -> LineEditor {
+        let history = history_path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|s| s.lines().map(String::from).collect())
+            .unwrap_or_default();
+
+        LineEditor {
+            history,
+            history_path,
+        }
+    }
+
+    /// the default history file in the user's home directory
+    pub fn default_history_path() -> Option<PathBuf> {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".csv_query_history"))
+    }
+
+    pub fn history(&self) -> &Vec<String> {
+        &self.history
+    }
+
+    /// adds a line to the history and the history file
+    pub fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(String::from(line));
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+
+        if let Some(path) = &self.history_path {
+            let mut content = self.history.join("\n");
+            content.push('\n');
+            if let Err(e) = fs::write(path, content) {
+                println!("failed to write history {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// reads a line, returns None at end of input
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        completer: &dyn Completer,
+    ) -> io::Result<Option<String>> {
+        let mut stdout = io::stdout();
+        let raw = match io::stdin().is_terminal() {
+            true => RawMode::enable(),
+            false => None,
+        };
+        if raw.is_none() {
+            print!("{}", prompt);
+            stdout.flush()?;
+            let mut buffer = String::new();
+            if io::stdin().read_line(&mut buffer)? == 0 {
+                return Ok(None);
+            }
+            return Ok(Some(buffer));
+        }
+
+        let mut stdin = io::stdin().lock();
+        let mut state = LineState::new();
+        Self::redraw(&mut stdout, prompt, &state)?;
+
+        loop {
+            let key = match read_key(&mut stdin)? {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+            match state.handle(key, &self.history, completer) {
+                Action::Continue => (),
+                Action::Accept(line) => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(Some(line));
+                }
+                Action::Cancel => {
+                    write!(stdout, "^C\r\n")?;
+                    state = LineState::new();
+                }
+                Action::Eof => {
+                    write!(stdout, "\r\n")?;
+                    return Ok(None);
+                }
+                Action::ShowCandidates(candidates) => {
+                    write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                }
+            }
+            Self::redraw(&mut stdout, prompt, &state)?;
+        }
+    }
+
+    fn redraw(out: &mut impl Write, prompt: &str, state: &LineState) -> io::Result<()> {
+        let line = state.line();
+        write!(out, "\r{}{}\x1b[K", prompt, line)?;
+        let back = line.chars().count() - state.cursor();
+        if back > 0 {
+            write!(out, "\x1b[{}D", back)?;
+        }
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Words(Vec<&'static str>);
+
+    impl Completer for Words {
+        fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+            let start = line[..pos].rfind(' ').map(|i| i + 1).unwrap_or(0);
+            let word = &line[start..pos];
+            let candidates = self
+                .0
+                .iter()
+                .filter(|w| w.starts_with(word))
+                .map(|w| String::from(*w))
+                .collect();
+            (start, candidates)
+        }
+    }
+
+    fn type_keys(
+        state: &mut LineState,
+        keys: &[Key],
+        history: &[String],
+        completer: &dyn Completer,
+    ) -> Action {
+        let mut action = Action::Continue;
+        for key in keys {
+            action = state.handle(*key, history, completer);
+        }
+        action
+    }
+
+    fn chars(s: &str) -> Vec<Key> {
+        s.chars().map(Key::Char).collect()
+    }
+
+    #[test]
+    fn read_keys() {
+        let mut input: &[u8] = b"a\x1b[A\x1b[D\x7f\t\r\xc3\xa5\x1b[3~";
+        let mut keys = vec![];
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+
+        assert_eq!(
+            keys,
+            vec![
+                Key::Char('a'),
+                Key::Up,
+                Key::Left,
+                Key::Backspace,
+                Key::Tab,
+                Key::Enter,
+                Key::Char('å'),
+                Key::Delete,
+            ]
+        );
+    }
+
+    #[test]
+    fn edit_line() {
+        let completer = Words(vec![]);
+        let mut state = LineState::new();
+
+        let mut keys = chars("selct");
+        keys.extend([
+            Key::Left,
+            Key::Left,
+            Key::Char('e'),
+            Key::End,
+            Key::Char('!'),
+        ]);
+        keys.extend([Key::Backspace, Key::Home, Key::Delete, Key::Char('S')]);
+        type_keys(&mut state, &keys, &[], &completer);
+
+        assert_eq!(state.line(), "Select");
+        assert_eq!(state.cursor(), 1);
+        assert_eq!(
+            state.handle(Key::Enter, &[], &completer),
+            Action::Accept(String::from("Select"))
+        );
+    }
+
+    #[test]
+    fn browse_history() {
+        let completer = Words(vec![]);
+        let history = vec![String::from("first"), String::from("second")];
+        let mut state = LineState::new();
+
+        type_keys(&mut state, &chars("dra"), &history, &completer);
+        type_keys(&mut state, &[Key::Up], &history, &completer);
+        assert_eq!(state.line(), "second");
+        type_keys(&mut state, &[Key::Up, Key::Up], &history, &completer);
+        assert_eq!(state.line(), "first");
+        type_keys(&mut state, &[Key::Down], &history, &completer);
+        assert_eq!(state.line(), "second");
+        type_keys(&mut state, &[Key::Down], &history, &completer);
+        assert_eq!(state.line(), "dra");
+    }
+
+    #[test]
+    fn tab_completion() {
+        let completer = Words(vec!["select", "deselect", "sum", "summary"]);
+        let mut state = LineState::new();
+
+        type_keys(&mut state, &chars("sel"), &[], &completer);
+        type_keys(&mut state, &[Key::Tab], &[], &completer);
+        assert_eq!(state.line(), "select");
+
+        type_keys(&mut state, &chars(" s"), &[], &completer);
+        let action = type_keys(&mut state, &[Key::Tab, Key::Tab], &[], &completer);
+        assert_eq!(
+            action,
+            Action::ShowCandidates(vec![
+                String::from("select"),
+                String::from("sum"),
+                String::from("summary"),
+            ])
+        );
+
+        type_keys(&mut state, &[Key::Char('u'), Key::Tab], &[], &completer);
+        assert_eq!(state.line(), "select sum");
+    }
+}
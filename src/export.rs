@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::Path;
+
+use crate::format::Format;
+use crate::model::{DataType, Record};
+
+#[derive(Debug)]
+pub struct ExportError {
+    pub message: String,
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// picks the output format from the file extension,
+/// anything not json, ndjson or markdown is written as csv
+pub fn format_for(path: &Path) -> Format {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => Format::Json,
+        Some("ndjson" | "jsonl") => Format::Ndjson,
+        Some("md") => Format::Markdown,
+        _ => Format::Csv,
+    }
+}
+
+/// keeps only the wanted columns of the records, in the wanted order
+pub fn project(
+    table_columns: &[String],
+    records: &[&Record],
+    columns: Option<&[String]>,
+) -> Result<(Vec<String>, Vec<Vec<DataType>>), ExportError> {
+    let columns = match columns {
+        None => table_columns.to_vec(),
+        Some(columns) => columns.to_vec(),
+    };
+    let indices = columns
+        .iter()
+        .map(|c| {
+            table_columns
+                .iter()
+                .position(|t| t == c)
+                .ok_or_else(|| ExportError {
+                    message: format!("no column named {}", c),
+                })
+        })
+        .collect::<Result<Vec<usize>, ExportError>>()?;
+
+    let rows = records
+        .iter()
+        .map(|r| indices.iter().map(|i| r[*i].clone()).collect())
+        .collect();
+    Ok((columns, rows))
+}
+
+/// writes records to a file in the format given by its extension,
+/// returning the number of records written
+///
+/// values are written with `DataType`'s `Display`, so decimals
+/// keep the precision they were loaded with
+pub fn export(
+    path: &Path,
+    table_columns: &[String],
+    records: &[&Record],
+    columns: Option<&[String]>,
+) -> Result<usize, ExportError> {
+    let (columns, rows) = project(table_columns, records, columns)?;
+    fs::write(path, format_for(path).format(&columns, &rows)).map_err(|e| ExportError {
+        message: format!("failed to write {}: {}", path.display(), e),
+    })?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::fixture_model;
+    use crate::model::Selection;
+
+    #[test]
+    fn export_associated_records() {
+        let model = fixture_model();
+
+        let mut ctx = model.new_data_context();
+        ctx.select(&Selection {
+            column: String::from("country"),
+            value: String::from("swe"),
+        });
+        let table = model.get_table("ages").unwrap();
+        let records = ctx.get_possible_records(table);
+
+        let dir = std::env::temp_dir();
+        let csv = dir.join(format!("export-test-ages-{}.csv", std::process::id()));
+        let columns = [String::from("age"), String::from("name")];
+        assert_eq!(
+            export(&csv, table.get_columns(), &records, Some(&columns)).unwrap(),
+            2
+        );
+        assert_eq!(
+            fs::read_to_string(&csv).unwrap(),
+            "age,name\n35,ni\n1.5,ai\n"
+        );
+
+        let json = dir.join(format!("export-test-ages-{}.json", std::process::id()));
+        export(&json, table.get_columns(), &records, None).unwrap();
+        assert_eq!(
+            fs::read_to_string(&json).unwrap(),
+            "[{\"name\":\"ni\",\"age\":35},{\"name\":\"ai\",\"age\":1.5}]\n"
+        );
+
+        let missing = [String::from("height")];
+        assert!(export(&csv, table.get_columns(), &records, Some(&missing)).is_err());
+
+        fs::remove_file(csv).unwrap();
+        fs::remove_file(json).unwrap();
+    }
+}
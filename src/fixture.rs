@@ -0,0 +1,32 @@
+//! the model shared by the tests of several modules
+
+use crate::model::{DataType, Model, Table};
+
+/// people and their country, with their ages in a second table
+/// associated by name
+pub(crate) fn fixture_model() -> Model {
+    let mut people = Table::new(
+        "people",
+        vec![String::from("name"), String::from("country")],
+    );
+    let mut ages = Table::new("ages", vec![String::from("name"), String::from("age")]);
+    for (name, country, age) in [
+        ("ni", "swe", "35"),
+        ("si", "cn", "34"),
+        ("ai", "swe", "1.5"),
+    ] {
+        people.insert(vec![
+            DataType::from_string(name),
+            DataType::from_string(country),
+        ]);
+        ages.insert(vec![
+            DataType::from_string(name),
+            DataType::from_string(age),
+        ]);
+    }
+
+    let mut model = Model::new();
+    model.add_table(people);
+    model.add_table(ages);
+    model
+}
@@ -0,0 +1,286 @@
+use crate::json::JsonValue;
+use crate::model::DataType;
+
+/// cells wider than this are truncated in aligned tables
+const MAX_CELL_WIDTH: usize = 40;
+
+/// renders a result set of named columns and rows of values
+pub trait Formatter {
+    fn format(&self, columns: &[String], rows: &[Vec<DataType>]) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+    Ndjson,
+    Markdown,
+}
+
+pub const FORMATS: [&str; 5] = ["table", "csv", "json", "ndjson", "markdown"];
+
+impl Format {
+    pub fn from_string(s: &str) -> Option<Format> {
+        match s {
+            "table" => Some(Format::Table),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "ndjson" => Some(Format::Ndjson),
+            "markdown" | "md" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+
+    pub fn formatter(&self) -> Box<dyn Formatter> {
+        match self {
+            Format::Table => Box::new(TableFormatter {
+                max_width: MAX_CELL_WIDTH,
+            }),
+            Format::Csv => Box::new(CsvFormatter),
+            Format::Json => Box::new(JsonFormatter { lines: false }),
+            Format::Ndjson => Box::new(JsonFormatter { lines: true }),
+            Format::Markdown => Box::new(MarkdownFormatter),
+        }
+    }
+
+    pub fn format(&self, columns: &[String], rows: &[Vec<DataType>]) -> String {
+        self.formatter().format(columns, rows)
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Format::Table => "table",
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Ndjson => "ndjson",
+            Format::Markdown => "markdown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+fn is_number(value: &DataType) -> bool {
+    matches!(value, DataType::Int(_) | DataType::Decimal(_, _))
+}
+
+fn truncate(s: &str, max_width: usize) -> String {
+    if s.chars().count() <= max_width {
+        return String::from(s);
+    }
+    let mut out: String = s.chars().take(max_width.saturating_sub(3)).collect();
+    out.push_str("...");
+    out
+}
+
+/// aligned ascii table, numbers are right aligned
+pub struct TableFormatter {
+    pub max_width: usize,
+}
+
+impl Formatter for TableFormatter {
+    fn format(&self, columns: &[String], rows: &[Vec<DataType>]) -> String {
+        let header: Vec<String> = columns
+            .iter()
+            .map(|c| truncate(c, self.max_width))
+            .collect();
+        let cells: Vec<Vec<String>> = rows
+            .iter()
+            .map(|r| {
+                r.iter()
+                    .map(|v| truncate(&v.to_string(), self.max_width))
+                    .collect()
+            })
+            .collect();
+
+        let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+        for row in &cells {
+            for (i, cell) in row.iter().enumerate().take(widths.len()) {
+                widths[i] = widths[i].max(cell.chars().count());
+            }
+        }
+
+        let separator = widths
+            .iter()
+            .map(|w| "-".repeat(w + 2))
+            .collect::<Vec<String>>()
+            .join("+");
+        let separator = format!("+{}+\n", separator);
+
+        let line = |cells: Vec<String>| -> String { format!("|{}|\n", cells.join("|")) };
+
+        let mut out = separator.clone();
+        out.push_str(&line(
+            header
+                .iter()
+                .zip(&widths)
+                .map(|(h, w)| format!(" {:<w$} ", h, w = w))
+                .collect(),
+        ));
+        out.push_str(&separator);
+        for (row, values) in cells.iter().zip(rows) {
+            out.push_str(&line(
+                row.iter()
+                    .zip(&widths)
+                    .zip(values)
+                    .map(|((cell, w), value)| {
+                        if is_number(value) {
+                            format!(" {:>w$} ", cell, w = w)
+                        } else {
+                            format!(" {:<w$} ", cell, w = w)
+                        }
+                    })
+                    .collect(),
+            ));
+        }
+        if !rows.is_empty() {
+            out.push_str(&separator);
+        }
+        out
+    }
+}
+
+/// comma separated values, quoting fields as described in RFC 4180
+pub struct CsvFormatter;
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        String::from(s)
+    }
+}
+
+impl Formatter for CsvFormatter {
+    fn format(&self, columns: &[String], rows: &[Vec<DataType>]) -> String {
+        let mut out = String::new();
+        let header: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+        out.push_str(&header.join(","));
+        out.push('\n');
+        for row in rows {
+            let fields: Vec<String> = row.iter().map(|v| csv_field(&v.to_string())).collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// one object per row keyed by column, either as a single
+/// array or as newline delimited objects
+pub struct JsonFormatter {
+    pub lines: bool,
+}
+
+impl Formatter for JsonFormatter {
+    fn format(&self, columns: &[String], rows: &[Vec<DataType>]) -> String {
+        let objects = rows.iter().map(|row| {
+            JsonValue::Object(
+                columns
+                    .iter()
+                    .zip(row)
+                    .map(|(c, v)| (c.clone(), JsonValue::from(v)))
+                    .collect(),
+            )
+        });
+
+        if self.lines {
+            objects.map(|o| format!("{}\n", o)).collect()
+        } else {
+            format!("{}\n", JsonValue::Array(objects.collect()))
+        }
+    }
+}
+
+/// github flavoured markdown table
+pub struct MarkdownFormatter;
+
+fn markdown_cell(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+impl Formatter for MarkdownFormatter {
+    fn format(&self, columns: &[String], rows: &[Vec<DataType>]) -> String {
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+
+        let mut out = line(columns.iter().map(|c| markdown_cell(c)).collect());
+        out.push_str(&line(
+            rows.first()
+                .map(|r| r.iter().map(is_number).collect())
+                .unwrap_or_else(|| vec![false; columns.len()])
+                .into_iter()
+                .map(|number| String::from(if number { "---:" } else { "---" }))
+                .collect(),
+        ));
+        for row in rows {
+            out.push_str(&line(
+                row.iter().map(|v| markdown_cell(&v.to_string())).collect(),
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result() -> (Vec<String>, Vec<Vec<DataType>>) {
+        let columns = vec![String::from("name"), String::from("age")];
+        let rows = vec![
+            vec![DataType::from_string("ni"), DataType::from_string("35")],
+            vec![
+                DataType::from_string("smith, \"jr\""),
+                DataType::from_string("1.5"),
+            ],
+        ];
+        (columns, rows)
+    }
+
+    #[test]
+    fn format_table() {
+        let (columns, rows) = result();
+        assert_eq!(
+            Format::Table.format(&columns, &rows),
+            "+-------------+-----+\n\
+             | name        | age |\n\
+             +-------------+-----+\n\
+             | ni          |  35 |\n\
+             | smith, \"jr\" | 1.5 |\n\
+             +-------------+-----+\n"
+        );
+
+        let narrow = TableFormatter { max_width: 6 };
+        assert!(narrow.format(&columns, &rows).contains("| smi... |"));
+    }
+
+    #[test]
+    fn format_csv_and_markdown() {
+        let (columns, rows) = result();
+        assert_eq!(
+            Format::Csv.format(&columns, &rows),
+            "name,age\nni,35\n\"smith, \"\"jr\"\"\",1.5\n"
+        );
+        assert_eq!(
+            Format::Markdown.format(&columns, &rows),
+            "| name | age |\n| --- | ---: |\n| ni | 35 |\n| smith, \"jr\" | 1.5 |\n"
+        );
+    }
+
+    #[test]
+    fn format_json() {
+        let (columns, rows) = result();
+        assert_eq!(
+            Format::Json.format(&columns, &rows[..1]),
+            "[{\"name\":\"ni\",\"age\":35}]\n"
+        );
+        assert_eq!(
+            Format::Ndjson.format(&columns, &rows),
+            "{\"name\":\"ni\",\"age\":35}\n{\"name\":\"smith, \\\"jr\\\"\",\"age\":1.5}\n"
+        );
+        assert_eq!(Format::from_string("md"), Some(Format::Markdown));
+        assert_eq!(Format::from_string("xml"), None);
+    }
+}
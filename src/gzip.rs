@@ -0,0 +1,392 @@
+use crate::checksum::crc32;
+
+#[derive(Debug)]
+pub struct GzipError {
+    pub message: String,
+}
+
+impl std::fmt::Display for GzipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+fn error(message: &str) -> GzipError {
+    GzipError {
+        message: format!("invalid gzip data: {}", message),
+    }
+}
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// the order code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// reads bits least significant first, as deflate stores them
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, GzipError> {
+        while self.bit_count < n {
+            let b = *self
+                .bytes
+                .get(self.pos)
+                .ok_or_else(|| error("unexpected end of data"))?;
+            self.pos += 1;
+            self.bit_buf |= (b as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let v = self.bit_buf & ((1u64 << n) - 1) as u32;
+        self.bit_buf >>= n;
+        self.bit_count -= n;
+        Ok(v)
+    }
+
+    /// drops the remaining bits of the current byte
+    fn align(&mut self) {
+        self.bit_buf = 0;
+        self.bit_count = 0;
+    }
+}
+
+/// a canonical huffman code, as the number of codes of each
+/// length and the symbols ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, GzipError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for l in lengths {
+            counts[*l as usize] += 1;
+        }
+
+        // reject over subscribed codes, incomplete ones are allowed
+        let mut left: i32 = 1;
+        for count in &counts[1..] {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err(error("over subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, l) in lengths.iter().enumerate() {
+            if *l != 0 {
+                symbols[offsets[*l as usize] as usize] = symbol as u16;
+                offsets[*l as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, r: &mut BitReader) -> Result<u16, GzipError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= r.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(error("invalid huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (i, l) in lengths.iter_mut().enumerate() {
+        *l = match i {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    let literals = Huffman::new(&lengths).expect("fixed codes are valid");
+    let distances = Huffman::new(&[5u8; 30]).expect("fixed codes are valid");
+    (literals, distances)
+}
+
+fn dynamic_codes(r: &mut BitReader) -> Result<(Huffman, Huffman), GzipError> {
+    let num_literals = r.bits(5)? as usize + 257;
+    let num_distances = r.bits(5)? as usize + 1;
+    let num_code_lengths = r.bits(4)? as usize + 4;
+    if num_literals > 286 || num_distances > 30 {
+        return Err(error("too many codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for i in &CODE_LENGTH_ORDER[..num_code_lengths] {
+        code_lengths[*i] = r.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![];
+    while lengths.len() < num_literals + num_distances {
+        let symbol = code_length_code.decode(r)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or_else(|| error("repeat without a previous length"))?;
+                (previous, 3 + r.bits(2)?)
+            }
+            17 => (0, 3 + r.bits(3)?),
+            _ => (0, 11 + r.bits(7)?),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > num_literals + num_distances {
+        return Err(error("code lengths overflow"));
+    }
+    if lengths[256] == 0 {
+        return Err(error("missing end of block code"));
+    }
+
+    let literals = Huffman::new(&lengths[..num_literals])?;
+    let distances = Huffman::new(&lengths[num_literals..])?;
+    Ok((literals, distances))
+}
+
+fn inflate_block(
+    r: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), GzipError> {
+    loop {
+        let symbol = literals.decode(r)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let i = symbol - 257;
+                if i >= LENGTH_BASE.len() {
+                    return Err(error("invalid length code"));
+                }
+                let length = LENGTH_BASE[i] as usize + r.bits(LENGTH_EXTRA[i] as u32)? as usize;
+
+                let d = distances.decode(r)? as usize;
+                if d >= DIST_BASE.len() {
+                    return Err(error("invalid distance code"));
+                }
+                let distance = DIST_BASE[d] as usize + r.bits(DIST_EXTRA[d] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(error("distance too far back"));
+                }
+
+                // copied a byte at a time since the source may overlap
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
+
+/// decompresses raw deflate data, returning the number of
+/// input bytes it took up
+pub fn inflate(bytes: &[u8], out: &mut Vec<u8>) -> Result<usize, GzipError> {
+    let mut r = BitReader {
+        bytes,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+    };
+
+    loop {
+        let last = r.bits(1)? == 1;
+        match r.bits(2)? {
+            0 => {
+                r.align();
+                let header = bytes
+                    .get(r.pos..r.pos + 4)
+                    .ok_or_else(|| error("unexpected end of data"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let nlen = u16::from_le_bytes([header[2], header[3]]) as usize;
+                if len != !nlen & 0xffff {
+                    return Err(error("stored block length mismatch"));
+                }
+                r.pos += 4;
+                let data = bytes
+                    .get(r.pos..r.pos + len)
+                    .ok_or_else(|| error("unexpected end of data"))?;
+                out.extend_from_slice(data);
+                r.pos += len;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut r, out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut r)?;
+                inflate_block(&mut r, out, &literals, &distances)?;
+            }
+            _ => return Err(error("invalid block type")),
+        }
+        if last {
+            return Ok(r.pos);
+        }
+    }
+}
+
+/// true if the bytes start with the gzip magic number
+pub fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0x1f, 0x8b])
+}
+
+const FHCRC: u8 = 2;
+const FEXTRA: u8 = 4;
+const FNAME: u8 = 8;
+const FCOMMENT: u8 = 16;
+
+/// decompresses gzip data as described in RFC 1952, concatenated
+/// members are decompressed one after the other
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let member = &bytes[pos..];
+        if member.len() < 18 || !is_gzip(member) {
+            return Err(error("missing gzip header"));
+        }
+        if member[2] != 8 {
+            return Err(error("unsupported compression method"));
+        }
+        let flags = member[3];
+        let mut i = 10;
+
+        let truncated = || error("truncated header");
+        if flags & FEXTRA != 0 {
+            let len = member.get(i..i + 2).ok_or_else(truncated)?;
+            i += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+        }
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                let end = member
+                    .get(i..)
+                    .and_then(|rest| rest.iter().position(|b| *b == 0))
+                    .ok_or_else(truncated)?;
+                i += end + 1;
+            }
+        }
+        if flags & FHCRC != 0 {
+            i += 2;
+        }
+        let data = member.get(i..).ok_or_else(truncated)?;
+
+        let mut decompressed = vec![];
+        i += inflate(data, &mut decompressed)?;
+
+        let trailer = member
+            .get(i..i + 8)
+            .ok_or_else(|| error("missing trailer"))?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+        if crc32(&decompressed) != crc {
+            return Err(error("checksum mismatch"));
+        }
+        if decompressed.len() as u32 != size {
+            return Err(error("size mismatch"));
+        }
+        out.extend(decompressed);
+        pos += i + 8;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const STORED: &str =
+        "1f8b08000000000000ff010f00f0ff6e616d652c6167650a6e692c33350a45e5490e0f000000";
+    const FIXED: &str =
+        "1f8b08080000000002ff612e63737600cb4bcc4dd5494c4fe5cacbd43136e5020045e5490e0f000000";
+
+    #[test]
+    fn decompress_stored_and_fixed_blocks() {
+        assert_eq!(decompress(&hex(STORED)).unwrap(), b"name,age\nni,35\n");
+        assert_eq!(decompress(&hex(FIXED)).unwrap(), b"name,age\nni,35\n");
+
+        let mut members = hex(STORED);
+        members.extend(hex(FIXED));
+        assert_eq!(
+            decompress(&members).unwrap(),
+            b"name,age\nni,35\nname,age\nni,35\n"
+        );
+    }
+
+    #[test]
+    fn decompress_dynamic_block() {
+        let compressed = hex(
+            "1f8b08000000000002ff35cebb0d44410843d19c5a08c0ccb79e97d37fb80cf2a658179d34fd4c4dd2f5\
+             737549e8078564bc7b488e771f92f3dda7e47af72569efbebb839eee4c6f77f5c73a84ba7769eae8b4a6\
+             e8b6a6d1714db3eb9a56e735edce6b3a9dd7743b2f99756e0aa719f8a3836a0cb231e9c6221c9b721cd2\
+             71690f233e9cf800f111c4c7203e26f1b1888f4d7c1ce2e3ca0fba62133c5e010000",
+        );
+        let expected: String = (0..40)
+            .map(|i| format!("n{},c{},{}\n", i % 7, i % 3, i))
+            .collect();
+        assert_eq!(decompress(&compressed).unwrap(), expected.as_bytes());
+    }
+
+    #[test]
+    fn reject_corrupt_data() {
+        let mut corrupt = hex(FIXED);
+        let n = corrupt.len();
+        corrupt[n - 8] ^= 1;
+        assert!(decompress(&corrupt)
+            .unwrap_err()
+            .message
+            .contains("checksum"));
+
+        assert!(decompress(&hex(FIXED)[..30]).is_err());
+        assert!(decompress(b"name,age\n").is_err());
+    }
+}
@@ -0,0 +1,391 @@
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use crate::ast::{Expression, Sort};
+use crate::json::JsonValue;
+use crate::model::{Model, Selection};
+use crate::server::{error, selections_from_json};
+
+const MAX_BODY: usize = 1024 * 1024;
+
+fn strings(values: Vec<&String>) -> JsonValue {
+    JsonValue::Array(
+        values
+            .into_iter()
+            .map(|s| JsonValue::String(s.clone()))
+            .collect(),
+    )
+}
+
+fn object(members: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        members
+            .into_iter()
+            .map(|(k, v)| (String::from(k), v))
+            .collect(),
+    )
+}
+
+/// decodes %xx escapes in a path segment
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// answers a request against the model, returning the
+/// status code and the JSON body
+///
+/// - `GET /tables` lists tables with their columns and row counts
+/// - `GET /columns` lists all columns of the model
+/// - `GET /columns/<column>/values` lists the distinct values of a column
+/// - `POST /query` evaluates expressions, optionally grouped, under a selection
+pub fn handle_request(model: &Model, method: &str, path: &str, body: &str) -> (u16, JsonValue) {
+    let path = path.split('?').next().unwrap_or("");
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["tables"]) => {
+            let tables = model
+                .get_tables()
+                .iter()
+                .map(|t| {
+                    object(vec![
+                        ("name", JsonValue::String(t.name.clone())),
+                        ("columns", strings(t.get_columns().iter().collect())),
+                        ("rows", JsonValue::Number(t.records.len().to_string())),
+                    ])
+                })
+                .collect();
+            (200, JsonValue::Array(tables))
+        }
+        ("GET", ["columns"]) => (200, strings(model.get_columns())),
+        ("GET", ["columns", column, "values"]) => {
+            if !model.get_columns().iter().any(|c| c == column) {
+                return (404, error(&format!("no column named {}", column)));
+            }
+            let values = model
+                .get_all_values(column)
+                .into_iter()
+                .map(JsonValue::from)
+                .collect();
+            (200, JsonValue::Array(values))
+        }
+        ("POST", ["query"]) => match query(model, body) {
+            Ok(result) => (200, result),
+            Err(message) => (400, error(&message)),
+        },
+        (_, ["tables"] | ["columns"] | ["columns", _, "values"] | ["query"]) => {
+            (405, error("method not allowed"))
+        }
+        _ => (404, error("not found")),
+    }
+}
+
+/// evaluates a query of the form
+/// `{"selections": [{"column": c, "values": [..]}], "group_by": [..], "expressions": [..]}`,
+/// optionally ordered and limited with `"sort": "sum(age) desc", "limit": 10, "offset": 0`
+fn query(model: &Model, body: &str) -> Result<JsonValue, String> {
+    let request = JsonValue::from_string(body).map_err(|e| format!("invalid request: {}", e))?;
+
+    let mut selection: Vec<Selection> = vec![];
+    if let Some(selections) = request.get("selections") {
+        let selections = selections.as_array().ok_or("selections must be a list")?;
+        for s in selections {
+            selection.extend(selections_from_json(s).ok_or("expected column and value or values")?);
+        }
+    }
+
+    let names = |key: &str| -> Result<Vec<String>, String> {
+        match request.get(key) {
+            None => Ok(vec![]),
+            Some(values) => values
+                .as_array()
+                .ok_or(format!("{} must be a list", key))?
+                .iter()
+                .map(|v| {
+                    v.as_str()
+                        .map(String::from)
+                        .ok_or(format!("{} must be strings", key))
+                })
+                .collect(),
+        }
+    };
+    let dims = names("group_by")?;
+    let mut exprs = vec![];
+    for text in names("expressions")? {
+        exprs.push(Expression::from_string(&text).map_err(|e| format!("{}: {}", text, e.message))?);
+    }
+
+    let mut sort = match request.get("sort").map(|s| s.as_str()) {
+        None => Sort::default(),
+        Some(Some(text)) => {
+            Sort::from_string(text).map_err(|e| format!("{}: {}", text, e.message))?
+        }
+        Some(None) => return Err(String::from("sort must be a string")),
+    };
+    let count = |key: &str| -> Result<Option<usize>, String> {
+        match request.get(key) {
+            None => Ok(None),
+            Some(JsonValue::Number(n)) => n
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("{} must be a whole number", key)),
+            Some(_) => Err(format!("{} must be a number", key)),
+        }
+    };
+    sort.limit = count("limit")?;
+    sort.offset = count("offset")?.unwrap_or(0);
+
+    let mut ctx = model.new_data_context();
+    ctx.apply(&selection);
+    let rows = ctx.group_by(&dims, &exprs, &sort).map_err(|e| e.message)?;
+
+    let mut columns = dims.clone();
+    columns.extend(exprs.iter().map(|e| e.to_string()));
+    let rows = rows
+        .into_iter()
+        .map(|(key, values)| {
+            JsonValue::Array(
+                key.iter()
+                    .chain(values.iter())
+                    .map(JsonValue::from)
+                    .collect(),
+            )
+        })
+        .collect();
+
+    Ok(object(vec![
+        ("columns", strings(columns.iter().collect())),
+        ("rows", JsonValue::Array(rows)),
+    ]))
+}
+
+fn status_text(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
+
+fn write_response(w: &mut impl Write, status: u16, body: &JsonValue) -> io::Result<()> {
+    let body = body.to_string();
+    write!(
+        w,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        status_text(status),
+        body.len(),
+        body
+    )?;
+    w.flush()
+}
+
+fn serve_request(stream: TcpStream, model: &Model) -> io::Result<()> {
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let parts: Vec<&str> = request_line.split_whitespace().collect();
+    let (method, path) = match parts.as_slice() {
+        [method, path, _] => (*method, *path),
+        _ => return write_response(&mut writer, 400, &error("invalid request line")),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_BODY {
+        return write_response(&mut writer, 413, &error("request body too large"));
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body)?;
+    let body = String::from_utf8_lossy(&body);
+
+    let (status, response) = handle_request(model, method, path, &body);
+    write_response(&mut writer, status, &response)
+}
+
+/// serves stateless JSON queries over HTTP, one request per connection
+pub struct HttpServer {
+    listener: TcpListener,
+}
+
+impl HttpServer {
+    pub fn bind(addr: &str) -> io::Result<HttpServer> {
+        Ok(HttpServer {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// accepts connections until the listener fails
+    pub fn run(&self, model: Arc<Model>) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let model = model.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve_request(stream, &model) {
+                    println!("http request failed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::fixture_model;
+
+    #[test]
+    fn list_tables_columns_and_values() {
+        let model = fixture_model();
+
+        let (status, body) = handle_request(&model, "GET", "/tables", "");
+        assert_eq!(status, 200);
+        assert_eq!(
+            body.to_string(),
+            r#"[{"name":"people","columns":["name","country"],"rows":3},{"name":"ages","columns":["name","age"],"rows":3}]"#
+        );
+
+        let (_, body) = handle_request(&model, "GET", "/columns", "");
+        assert_eq!(body.to_string(), r#"["age","country","name"]"#);
+
+        let (_, body) = handle_request(&model, "GET", "/columns/country/values", "");
+        assert_eq!(body.to_string(), r#"["cn","swe"]"#);
+
+        assert_eq!(
+            handle_request(&model, "GET", "/columns/nope/values", "").0,
+            404
+        );
+        assert_eq!(handle_request(&model, "GET", "/nope", "").0, 404);
+        assert_eq!(handle_request(&model, "DELETE", "/tables", "").0, 405);
+    }
+
+    #[test]
+    fn query_with_selection() {
+        let model = fixture_model();
+
+        let body = r#"{"selections": [{"column": "country", "values": ["swe"]}],
+                       "group_by": ["name"], "expressions": ["sum(age)", "count()"]}"#;
+        let (status, result) = handle_request(&model, "POST", "/query", body);
+
+        assert_eq!(status, 200);
+        assert_eq!(
+            result.to_string(),
+            r#"{"columns":["name","sum(age)","count()"],"rows":[["ai",1.5,1],["ni",35,1]]}"#
+        );
+
+        let (status, result) =
+            handle_request(&model, "POST", "/query", r#"{"expressions": ["sum(age)"]}"#);
+        assert_eq!(status, 200);
+        assert_eq!(
+            result.to_string(),
+            r#"{"columns":["sum(age)"],"rows":[[70.5]]}"#
+        );
+
+        let body = r#"{"group_by": ["name"], "expressions": ["sum(age)"],
+                       "sort": "sum(age) desc", "limit": 2, "offset": 1}"#;
+        let (status, result) = handle_request(&model, "POST", "/query", body);
+        assert_eq!(status, 200);
+        assert_eq!(
+            result.to_string(),
+            r#"{"columns":["name","sum(age)"],"rows":[["si",34],["ai",1.5]]}"#
+        );
+        let (status, _) = handle_request(&model, "POST", "/query", r#"{"limit": -1}"#);
+        assert_eq!(status, 400);
+
+        let (status, _) = handle_request(&model, "POST", "/query", r#"{"expressions": ["sum("]}"#);
+        assert_eq!(status, 400);
+        let (status, _) = handle_request(&model, "POST", "/query", r#"{"group_by": ["height"]}"#);
+        assert_eq!(status, 400);
+
+        // trailing whitespace ends an expression rather than failing the request
+        let body = r#"{"expressions": ["sum( "], "sort": "count desc "}"#;
+        assert_eq!(handle_request(&model, "POST", "/query", body).0, 400);
+        let body = r#"{"expressions": ["count() "], "sort": "sum(age ) "}"#;
+        assert_eq!(handle_request(&model, "POST", "/query", body).0, 200);
+
+        // nesting is bounded rather than overflowing the stack
+        let (status, _) = handle_request(&model, "POST", "/query", &"[".repeat(200_000));
+        assert_eq!(status, 400);
+        let body = format!(r#"{{"expressions": ["{}"]}}"#, "add(".repeat(50_000));
+        assert_eq!(handle_request(&model, "POST", "/query", &body).0, 400);
+    }
+
+    #[test]
+    fn serve_loopback_request() {
+        let server = HttpServer::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || server.run(Arc::new(fixture_model())));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let body = r#"{"expressions": ["count()"]}"#;
+        write!(
+            stream,
+            "POST /query HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(r#"{"columns":["count()"],"rows":[[3]]}"#));
+    }
+
+    #[test]
+    fn decode_path() {
+        assert_eq!(percent_decode("first%20name"), "first name");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
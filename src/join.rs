@@ -5,7 +5,7 @@ type Table<'a> = Vec<Vec<&'a str>>;
 pub struct JoinError {}
 
 
-fn join<'a, T1, T2>(t1: &'a Vec<T1>, t2: &'a Vec<T2>, predicate: impl Fn(&T1, &T2) -> bool) -> Vec<(&'a T1, &'a T2)> {
+fn join<'a, T1, T2>(t1: &'a [T1], t2: &'a [T2], predicate: impl Fn(&T1, &T2) -> bool) -> Vec<(&'a T1, &'a T2)> {
     let mut result: Vec<(&T1, &T2)> = vec![];
 
     for t1_row in t1.iter() {
@@ -16,12 +16,12 @@ fn join<'a, T1, T2>(t1: &'a Vec<T1>, t2: &'a Vec<T2>, predicate: impl Fn(&T1, &T
         }
     }
 
-    return result;
+    result
 }
 
 pub fn join_tables<'a>(t1: &'a Table, t2: &'a Table) -> Result<Table<'a>, JoinError> {
-    let h1 = t1.get(0).expect("t1 must have a header");
-    let h2 = t2.get(0).expect("t2 must have a header");
+    let h1 = t1.first().expect("t1 must have a header");
+    let h2 = t2.first().expect("t2 must have a header");
 
     let mut all_cols = h1.clone();
     let mut join_col_res = None;
@@ -29,7 +29,7 @@ pub fn join_tables<'a>(t1: &'a Table, t2: &'a Table) -> Result<Table<'a>, JoinEr
         if !all_cols.contains(col) {
             all_cols.push(col);
         } else {
-            if join_col_res != None {
+            if join_col_res.is_some() {
                 return Err(JoinError {  });
             }
             join_col_res = Some(*col);
@@ -37,7 +37,7 @@ pub fn join_tables<'a>(t1: &'a Table, t2: &'a Table) -> Result<Table<'a>, JoinEr
         }
     }
 
-    if join_col_res == None {
+    if join_col_res.is_none() {
         return Err(JoinError{});
     }
 
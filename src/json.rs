@@ -0,0 +1,397 @@
+use crate::model::DataType;
+
+/// a parsed JSON document
+///
+/// numbers are kept as their source text so that decimals
+/// round trip without going through a float
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseJsonError {
+    pub message: String,
+    pub position: usize,
+}
+
+impl std::fmt::Display for ParseJsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl JsonValue {
+    pub fn from_string(s: &str) -> Result<JsonValue, ParseJsonError> {
+        let mut parser = JsonParser::new(s);
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.peek().is_some() {
+            return Err(parser.error("Expected end of input"));
+        }
+        Ok(value)
+    }
+
+    /// get a member of an object by key
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, JsonValue)>> {
+        match self {
+            JsonValue::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+impl From<&DataType> for JsonValue {
+    fn from(value: &DataType) -> JsonValue {
+        match value {
+            DataType::Int(_) | DataType::Decimal(_, _) => JsonValue::Number(value.to_string()),
+            DataType::String(s) => JsonValue::String(s.to_string()),
+            DataType::Date(_) | DataType::Timestamp(_) => JsonValue::String(value.to_string()),
+            DataType::Bool(b) => JsonValue::Bool(*b),
+            DataType::Null => JsonValue::Null,
+        }
+    }
+}
+
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl std::fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(b) => write!(f, "{}", b),
+            JsonValue::Number(n) => write!(f, "{}", n),
+            JsonValue::String(s) => write_escaped(f, s),
+            JsonValue::Array(values) => {
+                write!(f, "[")?;
+                for (i, v) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+            JsonValue::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// how deeply objects and arrays may be nested, the parser
+/// recurses for each level, so this bounds its stack use
+const MAX_DEPTH: usize = 128;
+
+struct JsonParser {
+    chars: Vec<char>,
+    index: usize,
+    /// the objects and arrays the parser is in
+    depth: usize,
+}
+
+impl JsonParser {
+    fn new(s: &str) -> JsonParser {
+        JsonParser {
+            chars: s.chars().collect(),
+            index: 0,
+            depth: 0,
+        }
+    }
+
+    fn error(&self, message: &str) -> ParseJsonError {
+        ParseJsonError {
+            message: String::from(message),
+            position: self.index,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.index += 1;
+        c
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.index += 1;
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, ParseJsonError> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error("Unexpected character"));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, ParseJsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.parse_nested(JsonParser::parse_object),
+            Some('[') => self.parse_nested(JsonParser::parse_array),
+            Some('"') => self.parse_string().map(JsonValue::String),
+            Some('t') => self.expect_word("true", JsonValue::Bool(true)),
+            Some('f') => self.expect_word("false", JsonValue::Bool(false)),
+            Some('n') => self.expect_word("null", JsonValue::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    /// parses an object or array one level deeper, an error past `MAX_DEPTH`
+    fn parse_nested(
+        &mut self,
+        parse: fn(&mut JsonParser) -> Result<JsonValue, ParseJsonError>,
+    ) -> Result<JsonValue, ParseJsonError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("Nested too deeply"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, ParseJsonError> {
+        self.next();
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected object key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            if self.next() != Some(':') {
+                return Err(self.error("Expected ':'"));
+            }
+            let value = self.parse_value()?;
+            members.push((key, value));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => break,
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+        Ok(JsonValue::Object(members))
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, ParseJsonError> {
+        self.next();
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => break,
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+        Ok(JsonValue::Array(values))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, ParseJsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Invalid unicode escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseJsonError> {
+        self.next();
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                Some('"') => break,
+                Some('\\') => match self.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let mut code = self.parse_hex4()?;
+                        if (0xd800..0xdc00).contains(&code) {
+                            if self.next() != Some('\\') || self.next() != Some('u') {
+                                return Err(self.error("Expected low surrogate"));
+                            }
+                            let low = self.parse_hex4()?;
+                            code = 0x10000
+                                + ((code - 0xd800) << 10)
+                                + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        s.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    _ => return Err(self.error("Invalid escape")),
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+        Ok(s)
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, ParseJsonError> {
+        let start = self.index;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_digit() || c == '-' || c == '+' || c == '.' || c == 'e' || c == 'E') {
+                break;
+            }
+            self.index += 1;
+        }
+        let number: String = self.chars[start..self.index].iter().collect();
+        if number.parse::<f64>().is_err() {
+            return Err(ParseJsonError {
+                message: format!("Invalid number {}", number),
+                position: start,
+            });
+        }
+        Ok(JsonValue::Number(number))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_json_1() {
+        let v = JsonValue::from_string(r#"{"a": [1, 2.50, "x"], "b": null, "c": true}"#).unwrap();
+
+        assert_eq!(
+            v.get("a"),
+            Some(&JsonValue::Array(vec![
+                JsonValue::Number(String::from("1")),
+                JsonValue::Number(String::from("2.50")),
+                JsonValue::String(String::from("x")),
+            ]))
+        );
+        assert_eq!(v.get("b"), Some(&JsonValue::Null));
+        assert_eq!(v.get("c"), Some(&JsonValue::Bool(true)));
+        assert_eq!(v.get("d"), None);
+    }
+
+    #[test]
+    fn parse_json_errors() {
+        assert!(JsonValue::from_string("{").is_err());
+        assert!(JsonValue::from_string("[1,]").is_err());
+        assert!(JsonValue::from_string("1 2").is_err());
+        assert!(JsonValue::from_string("\"abc").is_err());
+    }
+
+    #[test]
+    fn parse_json_depth() {
+        let nested = |n: usize| format!("{}{}", "[".repeat(n), "]".repeat(n));
+        assert!(JsonValue::from_string(&nested(MAX_DEPTH)).is_ok());
+        let err = JsonValue::from_string(&nested(MAX_DEPTH + 1)).unwrap_err();
+        assert_eq!(err.message, "Nested too deeply");
+        assert_eq!(err.position, MAX_DEPTH);
+
+        let deep = "{\"a\":".repeat(200_000);
+        assert!(JsonValue::from_string(&deep).is_err());
+        assert!(JsonValue::from_string(&"[".repeat(200_000)).is_err());
+    }
+
+    #[test]
+    fn json_to_string() {
+        let v = JsonValue::Object(vec![
+            (
+                String::from("name"),
+                JsonValue::String(String::from("a \"b\"\n")),
+            ),
+            (
+                String::from("values"),
+                JsonValue::Array(vec![JsonValue::Null]),
+            ),
+        ]);
+
+        assert_eq!(v.to_string(), r#"{"name":"a \"b\"\n","values":[null]}"#);
+        assert_eq!(JsonValue::from_string(&v.to_string()), Ok(v));
+    }
+
+    #[test]
+    fn parse_json_unicode_escape() {
+        let v = JsonValue::from_string(r#""\u00e5\ud83d\ude00""#).unwrap();
+
+        assert_eq!(v.as_str(), Some("å😀"));
+    }
+}
@@ -0,0 +1,25 @@
+pub mod ast;
+pub mod bookmark;
+pub mod calendar;
+pub mod checksum;
+pub mod date;
+pub mod decimal;
+pub mod editor;
+pub mod export;
+pub mod format;
+pub mod gzip;
+pub mod http;
+pub mod join;
+pub mod json;
+pub mod load;
+pub mod mmap;
+pub mod model;
+pub mod profile;
+pub mod repl;
+pub mod server;
+pub mod snapshot;
+pub mod stream;
+pub mod websocket;
+
+#[cfg(test)]
+mod fixture;
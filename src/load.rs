@@ -0,0 +1,1219 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Read};
+use std::path::Path;
+use std::thread;
+
+use crate::decimal;
+use crate::decimal::NumberFormat;
+use crate::gzip;
+use crate::json::JsonValue;
+use crate::mmap::Mmap;
+use crate::model::{Column, DataType, Record, Table};
+
+#[derive(Debug)]
+pub struct LoadError {
+    pub message: String,
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    Csv,
+    Json,
+    FixedWidth(Vec<FieldSpec>),
+}
+
+/// how the text of a field is turned into a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    /// int or decimal when the text is numeric, a string otherwise
+    Auto,
+    String,
+    Int,
+    Decimal,
+}
+
+impl FieldType {
+    pub fn from_string(s: &str) -> Option<FieldType> {
+        match s {
+            "auto" => Some(FieldType::Auto),
+            "string" => Some(FieldType::String),
+            "int" => Some(FieldType::Int),
+            "decimal" => Some(FieldType::Decimal),
+            _ => None,
+        }
+    }
+}
+
+/// a column of a fixed width file, `start` is the 1-based
+/// character position of its first character
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSpec {
+    pub name: Column,
+    pub start: usize,
+    pub width: usize,
+    pub field_type: FieldType,
+}
+
+impl InputFormat {
+    /// the format named on the command line, fixed width input
+    /// is given by its layout instead
+    pub fn from_string(s: &str) -> Option<InputFormat> {
+        match s {
+            "csv" => Some(InputFormat::Csv),
+            "json" | "ndjson" => Some(InputFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// the input format given by a file extension, if it is known,
+    /// a `.gz` extension is looked past
+    pub fn from_path(path: &Path) -> Option<InputFormat> {
+        let path = match (path.extension(), path.file_stem()) {
+            (Some(gz), Some(stem)) if gz == "gz" => Path::new(stem),
+            _ => path,
+        };
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Some(InputFormat::Csv),
+            Some("json" | "ndjson" | "jsonl") => Some(InputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+fn read_error(path: &str) -> impl Fn(io::Error) -> LoadError + '_ {
+    move |e| LoadError {
+        message: format!("failed to read {}: {}", path, e),
+    }
+}
+
+fn open_file(path: &str) -> Result<fs::File, LoadError> {
+    fs::File::open(path).map_err(|e| LoadError {
+        message: format!("failed to open file {}: {}", path, e),
+    })
+}
+
+fn decompress(path: &str, bytes: &[u8]) -> Result<Vec<u8>, LoadError> {
+    gzip::decompress(bytes).map_err(|e| LoadError {
+        message: format!("{}: {}", path, e),
+    })
+}
+
+/// maps a file, or reads stdin for `-`, decompressing
+/// it when it starts with the gzip magic number
+fn open(path: &str) -> Result<Mmap, LoadError> {
+    let input = if path == "-" {
+        let mut bytes = vec![];
+        io::stdin()
+            .read_to_end(&mut bytes)
+            .map_err(read_error(path))?;
+        Mmap::from(bytes)
+    } else {
+        Mmap::open(&open_file(path)?).map_err(read_error(path))?
+    };
+
+    if !gzip::is_gzip(&input) {
+        return Ok(input);
+    }
+    Ok(Mmap::from(decompress(path, &input)?))
+}
+
+/// loads a file into a table named after its path, `-` reads
+/// stdin into a table named `stdin`, gzipped input is
+/// decompressed whatever its name
+///
+/// files are mapped into memory and parsed in place, so only
+/// the values that end up in the table are allocated, csv on
+/// stdin is read a batch of records at a time
+pub fn load_file(
+    path: &str,
+    format: InputFormat,
+    numbers: &NumberFormat,
+) -> Result<Table, LoadError> {
+    if path == "-" && format == InputFormat::Csv {
+        return load_csv("stdin", &mut open_reader(path)?, ",", numbers);
+    }
+    let input = open(path)?;
+    let path = if path == "-" { "stdin" } else { path };
+    let text = std::str::from_utf8(&input).map_err(|e| LoadError {
+        message: format!("{} is not utf-8: {}", path, e),
+    })?;
+    match format {
+        InputFormat::Csv => load_csv_text(path, text, ",", numbers, available_threads()),
+        InputFormat::FixedWidth(layout) => {
+            load_fixed_width(path, &mut io::Cursor::new(text), &layout, numbers)
+        }
+        InputFormat::Json => load_json(path, text),
+    }
+}
+
+/// opens a file, or stdin for `-`, to be read record by record
+/// without holding the whole input in memory
+///
+/// gzipped input is still decompressed into memory first, as
+/// the in-crate inflate works on whole buffers
+pub fn open_reader(path: &str) -> Result<Box<dyn BufRead>, LoadError> {
+    let mut r: Box<dyn BufRead> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(io::BufReader::new(open_file(path)?))
+    };
+
+    if !gzip::is_gzip(r.fill_buf().map_err(read_error(path))?) {
+        return Ok(r);
+    }
+    let mut bytes = vec![];
+    r.read_to_end(&mut bytes).map_err(read_error(path))?;
+    Ok(Box::new(io::Cursor::new(decompress(path, &bytes)?)))
+}
+
+/// reads delimited text one record at a time, the first record
+/// holds the column names and short records are padded with nulls
+pub struct CsvRecords<R> {
+    r: R,
+    name: String,
+    delim: String,
+    numbers: NumberFormat,
+    columns: Vec<Column>,
+    line: usize,
+}
+
+impl<R: BufRead> CsvRecords<R> {
+    pub fn new(
+        name: &str,
+        r: R,
+        delim: &str,
+        numbers: &NumberFormat,
+    ) -> Result<CsvRecords<R>, LoadError> {
+        let mut records = CsvRecords {
+            r,
+            name: String::from(name),
+            delim: String::from(delim),
+            numbers: *numbers,
+            columns: vec![],
+            line: 0,
+        };
+        let (_, header) = records.read_record()?.ok_or_else(|| LoadError {
+            message: String::from("file empty"),
+        })?;
+        records.columns = parse_csv_record(&header, delim)
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect();
+        Ok(records)
+    }
+
+    pub fn get_columns(&self) -> &Vec<Column> {
+        &self.columns
+    }
+
+    /// appends whole records, with their line endings, to the batch
+    /// until it has grown by at least `size` bytes or the input ends,
+    /// returns false when there was nothing left to read
+    fn read_batch(&mut self, batch: &mut String, size: usize) -> Result<bool, LoadError> {
+        let start = batch.len();
+        let mut scanner = RecordScanner::new(&self.delim);
+        let mut record_line = self.line + 1;
+        loop {
+            let end = batch.len();
+            match self.r.read_line(batch) {
+                Ok(0) if scanner.in_quotes => {
+                    return Err(line_error(
+                        &self.name,
+                        record_line,
+                        "unterminated quoted field",
+                    ))
+                }
+                Ok(0) => break,
+                Err(e) => return Err(line_error(&self.name, self.line + 1, &e.to_string())),
+                _ => self.line += 1,
+            }
+            if scanner.find_end(&batch[end..]).is_some() {
+                if batch.len() - start >= size {
+                    break;
+                }
+                record_line = self.line + 1;
+            }
+        }
+        Ok(batch.len() > start)
+    }
+
+    /// the line number and text of the next non-blank record, which
+    /// may span several lines when a quoted field holds newlines
+    fn read_record(&mut self) -> Result<Option<(usize, String)>, LoadError> {
+        loop {
+            let line = self.line + 1;
+            let mut record = String::new();
+            if !self.read_batch(&mut record, 1)? {
+                return Ok(None);
+            }
+            let len = record.trim_end_matches(['\n', '\r']).len();
+            record.truncate(len);
+            if !record.is_empty() {
+                return Ok(Some((line, record)));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvRecords<R> {
+    type Item = Result<Record, LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (line, text) = match self.read_record() {
+            Ok(record) => record?,
+            Err(e) => return Some(Err(e)),
+        };
+        let mut record = Record::with_capacity(self.columns.len());
+        for (field, column) in parse_csv_record(&text, &self.delim)
+            .iter()
+            .zip(&self.columns)
+        {
+            match coerce(field, FieldType::Auto, column, &self.numbers) {
+                Ok(value) => record.push(value),
+                Err(message) => return Some(Err(line_error(&self.name, line, &message))),
+            }
+        }
+        record.resize(self.columns.len(), DataType::Null);
+        Some(Ok(record))
+    }
+}
+
+fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+fn line_error(name: &str, line: usize, message: &str) -> LoadError {
+    LoadError {
+        message: format!("{} line {}: {}", name, line, message),
+    }
+}
+
+/// converts the text of a field to a value, empty int and
+/// decimal fields are null, numbers too large for a value
+/// are an error rather than text
+fn coerce(
+    field: &str,
+    field_type: FieldType,
+    column: &str,
+    numbers: &NumberFormat,
+) -> Result<DataType, String> {
+    match field_type {
+        FieldType::Auto => match DataType::parse(field, numbers) {
+            DataType::String(_) if decimal::is_numeric(field, numbers) => {
+                Err(format!("{} is out of range: {}", column, field))
+            }
+            value => Ok(value),
+        },
+        FieldType::String => Ok(DataType::String(field.into())),
+        FieldType::Int | FieldType::Decimal if field.is_empty() => Ok(DataType::Null),
+        FieldType::Int => match DataType::parse(field, numbers) {
+            DataType::Int(i) => Ok(DataType::Int(i)),
+            _ => Err(format!("{} is not an int: {}", column, field)),
+        },
+        FieldType::Decimal => match DataType::parse(field, numbers) {
+            DataType::String(_) => Err(format!("{} is not a decimal: {}", column, field)),
+            value => Ok(value),
+        },
+    }
+}
+
+/// reads the remaining lines of a file into a table, `parse`
+/// gets each line without its line ending and its 1-based
+/// line number
+fn load_lines(
+    mut t: Table,
+    r: &mut impl BufRead,
+    first_line: usize,
+    mut parse: impl FnMut(&str) -> Result<Record, String>,
+) -> Result<Table, LoadError> {
+    let mut buf = String::new();
+    let mut line = first_line;
+    loop {
+        match r.read_line(&mut buf) {
+            Ok(0) => break,
+            Err(e) => return Err(line_error(&t.name, line, &e.to_string())),
+            _ => (),
+        }
+        let record = parse(buf.trim_end_matches(['\n', '\r']))
+            .map_err(|message| line_error(&t.name, line, &message))?;
+        t.insert(record);
+        buf.clear();
+        line += 1;
+    }
+    Ok(t)
+}
+
+/// splits a record into fields, fields may be quoted with `"`
+/// to hold delimiters, newlines and `""` for a quote, unquoted
+/// fields have trailing whitespace removed
+fn parse_csv_record<'t>(record: &'t str, delim: &str) -> Vec<Cow<'t, str>> {
+    if !record.contains('"') {
+        return record
+            .split(delim)
+            .map(|s| Cow::Borrowed(s.trim_end()))
+            .collect();
+    }
+
+    let mut fields = vec![];
+    let mut rest = record;
+    loop {
+        let (field, next) = match rest.strip_prefix('"') {
+            Some(quoted) => parse_quoted_field(quoted, delim),
+            None => match rest.find(delim) {
+                Some(i) => (
+                    Cow::Borrowed(rest[..i].trim_end()),
+                    Some(&rest[i + delim.len()..]),
+                ),
+                None => (Cow::Borrowed(rest.trim_end()), None),
+            },
+        };
+        fields.push(field);
+        match next {
+            Some(next) => rest = next,
+            None => return fields,
+        }
+    }
+}
+
+/// parses a field after its opening quote, returning it and the rest of
+/// the record after its delimiter, the field is a slice of the record
+/// unless it has `""` or text after the closing quote to join
+fn parse_quoted_field<'t>(quoted: &'t str, delim: &str) -> (Cow<'t, str>, Option<&'t str>) {
+    let mut field = Cow::Borrowed("");
+    let mut rest = quoted;
+    loop {
+        let Some(i) = rest.find('"') else {
+            // an unclosed quote runs to the end of the record
+            push_str(&mut field, rest);
+            return (field, None);
+        };
+        push_str(&mut field, &rest[..i]);
+        rest = &rest[i + 1..];
+        match rest.strip_prefix('"') {
+            Some(after) => {
+                push_str(&mut field, "\"");
+                rest = after;
+            }
+            None => break,
+        }
+    }
+    match rest.find(delim) {
+        Some(i) => {
+            push_str(&mut field, &rest[..i]);
+            (field, Some(&rest[i + delim.len()..]))
+        }
+        None => {
+            push_str(&mut field, rest);
+            (field, None)
+        }
+    }
+}
+
+fn push_str<'t>(field: &mut Cow<'t, str>, s: &'t str) {
+    if field.is_empty() {
+        *field = Cow::Borrowed(s);
+    } else if !s.is_empty() {
+        field.to_mut().push_str(s);
+    }
+}
+
+/// finds where the records of delimited text end, a `"` opens a
+/// quoted section only as the first character of a field, like in
+/// `parse_csv_record`, anywhere else it is part of the field
+struct RecordScanner<'d> {
+    delim: &'d [u8],
+    in_quotes: bool,
+    /// whether the next character is the first of a field
+    field_start: bool,
+    /// the number of newlines scanned, in and out of quotes
+    lines: usize,
+}
+
+impl RecordScanner<'_> {
+    fn new(delim: &str) -> RecordScanner<'_> {
+        RecordScanner {
+            delim: delim.as_bytes(),
+            in_quotes: false,
+            field_start: true,
+            lines: 0,
+        }
+    }
+
+    /// scans text that continues the current record, returning the
+    /// length up to and including the newline that ends the record,
+    /// none when the record goes on past the text
+    fn find_end(&mut self, text: &str) -> Option<usize> {
+        let bytes = text.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let b = bytes[i];
+            if self.in_quotes {
+                if b == b'"' {
+                    // `""` is a quote inside of a quoted section
+                    if bytes.get(i + 1) == Some(&b'"') {
+                        i += 2;
+                        continue;
+                    }
+                    self.in_quotes = false;
+                } else if b == b'\n' {
+                    self.lines += 1;
+                }
+            } else if b == b'\n' {
+                self.field_start = true;
+                self.lines += 1;
+                return Some(i + 1);
+            } else if bytes[i..].starts_with(self.delim) {
+                self.field_start = true;
+                i += self.delim.len();
+                continue;
+            } else if b == b'"' && self.field_start {
+                self.in_quotes = true;
+            }
+            self.field_start = false;
+            i += 1;
+        }
+        None
+    }
+}
+
+/// the next non-blank record of the text without its line ending,
+/// the number of lines scanned before it and the text after it
+fn next_record<'t>(
+    scanner: &mut RecordScanner<'_>,
+    text: &'t str,
+) -> Option<(&'t str, usize, &'t str)> {
+    let mut rest = text;
+    while !rest.is_empty() {
+        let lines = scanner.lines;
+        let (record, after) = match scanner.find_end(rest) {
+            Some(end) => (&rest[..end - 1], &rest[end..]),
+            None => (rest, ""),
+        };
+        let record = record.trim_end_matches('\r');
+        if !record.is_empty() {
+            return Some((record, lines, after));
+        }
+        rest = after;
+    }
+    None
+}
+
+/// calls `f` with each record of the text, records end at a newline
+/// outside of quotes and blank records are skipped, errors are
+/// reported at the line a record starts on, the text starting on
+/// `first_line`
+fn for_each_record<'t>(
+    name: &str,
+    text: &'t str,
+    first_line: usize,
+    delim: &str,
+    mut f: impl FnMut(&'t str) -> Result<(), String>,
+) -> Result<(), LoadError> {
+    let mut scanner = RecordScanner::new(delim);
+    let mut rest = text;
+    while let Some((record, lines, after)) = next_record(&mut scanner, rest) {
+        let line = first_line + lines;
+        if scanner.in_quotes {
+            return Err(line_error(name, line, "unterminated quoted field"));
+        }
+        f(record).map_err(|message| line_error(name, line, &message))?;
+        rest = after;
+    }
+    Ok(())
+}
+
+/// the column names from the first non-blank record of delimited
+/// text, with the text after it and the line that starts on
+fn split_header<'t>(
+    name: &str,
+    text: &'t str,
+    delim: &str,
+) -> Result<(Vec<Column>, &'t str, usize), LoadError> {
+    let mut scanner = RecordScanner::new(delim);
+    let (header, lines, body) = next_record(&mut scanner, text).ok_or_else(|| LoadError {
+        message: String::from("file empty"),
+    })?;
+    if scanner.in_quotes {
+        return Err(line_error(name, lines + 1, "unterminated quoted field"));
+    }
+    let columns = parse_csv_record(header, delim)
+        .into_iter()
+        .map(Cow::into_owned)
+        .collect();
+    Ok((columns, body, scanner.lines + 1))
+}
+
+/// splits text into about `n` chunks that each end on a record
+/// boundary, with the number of lines before each chunk
+fn split_chunks<'t>(text: &'t str, delim: &str, n: usize) -> Vec<(usize, &'t str)> {
+    let size = text.len() / n.max(1) + 1;
+    let mut scanner = RecordScanner::new(delim);
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut start_line = 0;
+    let mut end = 0;
+    while let Some(len) = scanner.find_end(&text[end..]) {
+        end += len;
+        if end - start >= size {
+            chunks.push((start_line, &text[start..end]));
+            start = end;
+            start_line = scanner.lines;
+        }
+    }
+    if start < text.len() {
+        chunks.push((start_line, &text[start..]));
+    }
+    chunks
+}
+
+/// the index of a chunk, keyed by column position and value
+type ChunkIndex = HashMap<(usize, DataType), Vec<usize>>;
+
+/// parses the records of a chunk starting on `first_line` and indexes
+/// them by their position within the chunk, short records are padded
+/// with nulls
+///
+/// each distinct field text is converted once and its value shared
+/// by all the cells holding it, so repeated strings are
+/// allocated once per chunk
+fn parse_chunk(
+    name: &str,
+    chunk: &str,
+    first_line: usize,
+    delim: &str,
+    numbers: &NumberFormat,
+    columns: &[Column],
+) -> Result<(Vec<Record>, ChunkIndex), LoadError> {
+    let mut records = vec![];
+    let mut index: ChunkIndex = HashMap::new();
+    let mut dictionary: HashMap<Cow<str>, DataType> = HashMap::new();
+    for_each_record(name, chunk, first_line, delim, |line| {
+        let mut record = Record::with_capacity(columns.len());
+        for (field, column) in parse_csv_record(line, delim).into_iter().zip(columns) {
+            let value = match dictionary.get(field.as_ref()) {
+                Some(value) => value.clone(),
+                // csv fields have no declared types, so a text has the
+                // same value in every column
+                None => {
+                    let value = coerce(&field, FieldType::Auto, column, numbers)?;
+                    dictionary.insert(field, value.clone());
+                    value
+                }
+            };
+            record.push(value);
+        }
+        record.resize(columns.len(), DataType::Null);
+
+        for (i, value) in record.iter().enumerate() {
+            if *value != DataType::Null {
+                index
+                    .entry((i, value.clone()))
+                    .or_default()
+                    .push(records.len());
+            }
+        }
+        records.push(record);
+        Ok(())
+    })?;
+    Ok((records, index))
+}
+
+/// the records and index of delimited text that is parsed
+/// a batch of whole records at a time
+struct CsvLoader<'a> {
+    name: &'a str,
+    delim: &'a str,
+    numbers: &'a NumberFormat,
+    threads: usize,
+    columns: Vec<Column>,
+    records: Vec<Record>,
+    index: ChunkIndex,
+}
+
+impl<'a> CsvLoader<'a> {
+    fn new(
+        name: &'a str,
+        delim: &'a str,
+        numbers: &'a NumberFormat,
+        threads: usize,
+        columns: Vec<Column>,
+    ) -> CsvLoader<'a> {
+        CsvLoader {
+            name,
+            delim,
+            numbers,
+            threads,
+            columns,
+            records: vec![],
+            index: HashMap::new(),
+        }
+    }
+
+    /// adds the records of a batch starting on `first_line`, the batch
+    /// is split into chunks that are parsed and indexed on the threads
+    fn add(&mut self, batch: &str, first_line: usize) -> Result<(), LoadError> {
+        let (name, delim, numbers, columns) = (self.name, self.delim, self.numbers, &self.columns);
+        let chunks = split_chunks(batch, delim, self.threads);
+        let parsed: Vec<Result<(Vec<Record>, ChunkIndex), LoadError>> = thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .iter()
+                .map(|&(lines, chunk)| {
+                    scope.spawn(move || {
+                        parse_chunk(name, chunk, first_line + lines, delim, numbers, columns)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("loader thread should not panic"))
+                .collect()
+        });
+
+        // chunks are merged in order, so row ids stay ascending
+        for result in parsed {
+            let (chunk_records, chunk_index) = result?;
+            let offset = self.records.len();
+            for (key, rows) in chunk_index {
+                self.index
+                    .entry(key)
+                    .or_default()
+                    .extend(rows.into_iter().map(|row| row + offset));
+            }
+            self.records.extend(chunk_records);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Table {
+        let columns = self.columns;
+        let index = self
+            .index
+            .into_iter()
+            .map(|((i, value), rows)| (columns[i].clone(), value.to_string(), rows))
+            .collect();
+        Table::from_parts(self.name, columns, self.records, index)
+    }
+}
+
+/// loads delimited text where the first record holds the column
+/// names, the rest is split into chunks that are parsed and
+/// indexed on `threads` threads and then merged in order
+pub fn load_csv_text(
+    name: &str,
+    text: &str,
+    delim: &str,
+    numbers: &NumberFormat,
+    threads: usize,
+) -> Result<Table, LoadError> {
+    let (columns, body, body_line) = split_header(name, text, delim)?;
+    let mut loader = CsvLoader::new(name, delim, numbers, threads, columns);
+    loader.add(body, body_line)?;
+    Ok(loader.finish())
+}
+
+/// how many bytes of text `load_csv` reads before parsing them
+const BATCH_SIZE: usize = 16 << 20;
+
+/// loads delimited text, see `load_csv_text`, using a thread per
+/// available core, the input is read and parsed a batch of records
+/// at a time so it is never held in memory as a whole
+pub fn load_csv(
+    name: &str,
+    r: &mut impl BufRead,
+    delim: &str,
+    numbers: &NumberFormat,
+) -> Result<Table, LoadError> {
+    load_csv_batches(name, r, delim, numbers, available_threads(), BATCH_SIZE)
+}
+
+fn load_csv_batches(
+    name: &str,
+    r: &mut impl BufRead,
+    delim: &str,
+    numbers: &NumberFormat,
+    threads: usize,
+    batch_size: usize,
+) -> Result<Table, LoadError> {
+    let mut reader = CsvRecords::new(name, r, delim, numbers)?;
+    let columns = reader.get_columns().clone();
+    let mut loader = CsvLoader::new(name, delim, numbers, threads, columns);
+    let mut batch = String::new();
+    loop {
+        let first_line = reader.line + 1;
+        if !reader.read_batch(&mut batch, batch_size)? {
+            break;
+        }
+        loader.add(&batch, first_line)?;
+        batch.clear();
+    }
+    Ok(loader.finish())
+}
+
+/// parses a layout with one column per line as
+/// `<name> <start> <width> [auto|string|int|decimal]`,
+/// blank lines and lines starting with `#` are skipped
+pub fn parse_layout(text: &str) -> Result<Vec<FieldSpec>, LoadError> {
+    let mut layout = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| line_error("layout", n + 1, message);
+
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (name, start, width, field_type) = match parts.as_slice() {
+            [name, start, width] => (name, start, width, "auto"),
+            [name, start, width, field_type] => (name, start, width, *field_type),
+            _ => return Err(error("expected <name> <start> <width> [type]")),
+        };
+        let start = match start.parse() {
+            Ok(start) if start > 0 => start,
+            _ => return Err(error("start must be a position from 1")),
+        };
+        let width = match width.parse() {
+            Ok(width) if width > 0 => width,
+            _ => return Err(error("width must be a positive number")),
+        };
+        let field_type = FieldType::from_string(field_type)
+            .ok_or_else(|| error("type must be auto, string, int or decimal"))?;
+
+        layout.push(FieldSpec {
+            name: String::from(*name),
+            start,
+            width,
+            field_type,
+        });
+    }
+
+    if layout.is_empty() {
+        return Err(LoadError {
+            message: String::from("layout has no columns"),
+        });
+    }
+    Ok(layout)
+}
+
+/// loads fixed width text, every line is a record with its
+/// fields cut out by the layout and trimmed, fields past the
+/// end of a short line are empty
+pub fn load_fixed_width(
+    name: &str,
+    r: &mut impl BufRead,
+    layout: &[FieldSpec],
+    numbers: &NumberFormat,
+) -> Result<Table, LoadError> {
+    let columns = layout.iter().map(|f| f.name.clone()).collect();
+    let t = Table::new(name, columns);
+
+    load_lines(t, r, 1, |line| {
+        let chars: Vec<char> = line.chars().collect();
+        layout
+            .iter()
+            .map(|f| {
+                let start = (f.start - 1).min(chars.len());
+                let end = (start + f.width).min(chars.len());
+                let field: String = chars[start..end].iter().collect();
+                coerce(field.trim(), f.field_type, &f.name, numbers)
+            })
+            .collect()
+    })
+}
+
+/// adds the members of an object to a row, nested objects
+/// become dotted column names and arrays are kept as JSON text
+fn flatten(
+    prefix: &str,
+    members: &[(String, JsonValue)],
+    columns: &mut Vec<Column>,
+    row: &mut Vec<(usize, DataType)>,
+) -> Result<(), String> {
+    for (key, value) in members {
+        let column = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+
+        let value = match value {
+            JsonValue::Object(members) => {
+                flatten(&column, members, columns, row)?;
+                continue;
+            }
+            JsonValue::Null => DataType::Null,
+            JsonValue::Bool(b) => DataType::Bool(*b),
+            JsonValue::Number(n) => coerce(n, FieldType::Auto, &column, &NumberFormat::default())?,
+            // json has no dates, so they are strings in ISO 8601 form
+            JsonValue::String(s) => {
+                DataType::parse_date(s).unwrap_or_else(|| DataType::String(s.as_str().into()))
+            }
+            JsonValue::Array(_) => DataType::String(value.to_string().into()),
+        };
+
+        let i = match columns.iter().position(|c| *c == column) {
+            Some(i) => i,
+            None => {
+                columns.push(column);
+                columns.len() - 1
+            }
+        };
+        row.push((i, value));
+    }
+    Ok(())
+}
+
+/// loads a JSON array of objects, or newline delimited objects,
+/// into a table, columns missing from an object are null
+pub fn load_json(name: &str, content: &str) -> Result<Table, LoadError> {
+    let objects: Vec<JsonValue> = match JsonValue::from_string(content) {
+        Ok(JsonValue::Array(values)) => values,
+        Ok(value) => vec![value],
+        Err(_) => {
+            let mut values = vec![];
+            for (n, line) in content.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                values.push(JsonValue::from_string(line).map_err(|e| LoadError {
+                    message: format!("{} line {}: {}", name, n + 1, e),
+                })?);
+            }
+            values
+        }
+    };
+
+    let mut columns: Vec<Column> = vec![];
+    let mut rows = vec![];
+    for (n, object) in objects.iter().enumerate() {
+        let members = object.as_object().ok_or_else(|| LoadError {
+            message: format!("{} record {}: expected an object", name, n + 1),
+        })?;
+        let mut row = vec![];
+        flatten("", members, &mut columns, &mut row).map_err(|message| LoadError {
+            message: format!("{} record {}: {}", name, n + 1, message),
+        })?;
+        rows.push(row);
+    }
+
+    let mut t = Table::new(name, columns.clone());
+    for row in rows {
+        let mut record: Record = vec![DataType::Null; columns.len()];
+        for (i, value) in row {
+            record[i] = value;
+        }
+        t.insert(record);
+    }
+    Ok(t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::Selection;
+
+    #[test]
+    fn load_csv_lines() {
+        let mut r = io::Cursor::new("name,age\nni,35\nai,1.5\n");
+        let t = load_csv("people", &mut r, ",", &NumberFormat::default()).unwrap();
+        assert_eq!(t.columns, vec![String::from("name"), String::from("age")]);
+        assert_eq!(t.records[1][1], DataType::Decimal(15, 1));
+        assert!(load_csv(
+            "empty",
+            &mut io::Cursor::new(""),
+            ",",
+            &NumberFormat::default()
+        )
+        .is_err());
+
+        let mut r = io::Cursor::new("name;price\nni;1.234,50\nai;-,5\n");
+        let t = load_csv("prices", &mut r, ";", &NumberFormat::decimal_comma()).unwrap();
+        assert_eq!(t.records[0][1], DataType::Decimal(123450, 2));
+        assert_eq!(t.records[1][1], DataType::Decimal(-5, 1));
+
+        // numbers too large for a value are not text that sums to zero
+        let mut r = io::Cursor::new("n\n99999999999999999999\n1\n");
+        let e = load_csv("big", &mut r, ",", &NumberFormat::default())
+            .err()
+            .unwrap();
+        assert_eq!(
+            e.message,
+            "big line 2: n is out of range: 99999999999999999999"
+        );
+    }
+
+    #[test]
+    fn load_quoted_csv_in_chunks() {
+        let text = "name,\"note, long\"\r\n\
+                    ni,\"said \"\"hi\"\"\"\r\n\
+                    si,\"two\nlines\"\n\
+                    \n\
+                    ai\n\
+                    ni,35\n";
+        for threads in [1, 2, 8] {
+            let t = load_csv_text("notes", text, ",", &NumberFormat::default(), threads).unwrap();
+            assert_eq!(t.columns, vec!["name", "note, long"]);
+            assert_eq!(
+                t.records,
+                vec![
+                    vec![
+                        DataType::from_string("ni"),
+                        DataType::from_string("said \"hi\"")
+                    ],
+                    vec![
+                        DataType::from_string("si"),
+                        DataType::from_string("two\nlines")
+                    ],
+                    vec![DataType::from_string("ai"), DataType::Null],
+                    vec![DataType::from_string("ni"), DataType::Int(35)],
+                ]
+            );
+            let possible = t.get_possible(&[Selection {
+                column: String::from("name"),
+                value: String::from("ni"),
+            }]);
+            assert_eq!(possible, vec![&t.records[0], &t.records[3]]);
+        }
+        assert_eq!(
+            split_chunks("ab\n\"b\nc\"\nd\n", ",", 3),
+            vec![(0, "ab\n\"b\nc\"\n"), (3, "d\n")]
+        );
+
+        // a quote inside of an unquoted field is a plain character
+        let text = "name,age\nni 5\" tall,35\nsi,34\nai,\"1\"\n";
+        for threads in [1, 3] {
+            let t = load_csv_text("heights", text, ",", &NumberFormat::default(), threads).unwrap();
+            assert_eq!(
+                t.records,
+                vec![
+                    vec![DataType::from_string("ni 5\" tall"), DataType::Int(35)],
+                    vec![DataType::from_string("si"), DataType::Int(34)],
+                    vec![DataType::from_string("ai"), DataType::Int(1)],
+                ]
+            );
+        }
+        assert_eq!(
+            split_chunks("a\"\nb\n\"c,\"\"\nd\"\ne\n", ",", 4),
+            vec![(0, "a\"\nb\n"), (2, "\"c,\"\"\nd\"\n"), (4, "e\n")]
+        );
+    }
+
+    #[test]
+    fn parse_quoted_csv_fields() {
+        let fields = parse_csv_record("\"a,b\",\"say \"\"hi\"\"\",c ,\"d\"e ,\"\"", ",");
+        assert_eq!(fields, ["a,b", "say \"hi\"", "c", "de ", ""]);
+        assert!(matches!(fields[0], Cow::Borrowed(_)));
+        assert!(matches!(fields[2], Cow::Borrowed(_)));
+
+        // an unclosed quote runs to the end of the record
+        assert_eq!(parse_csv_record("a;\"b;c ", ";"), ["a", "b;c "]);
+        assert_eq!(parse_csv_record("a\"b,", ","), ["a\"b", ""]);
+    }
+
+    #[test]
+    fn load_csv_in_batches() {
+        let text = "name,note\nni,\"two\nlines\"\nsi,5\"\n\nai,x\n";
+        let whole = load_csv_text("notes", text, ",", &NumberFormat::default(), 1).unwrap();
+        assert_eq!(whole.records.len(), 3);
+        for batch_size in [1, 10, 1000] {
+            let mut r = io::Cursor::new(text);
+            let t = load_csv_batches(
+                "notes",
+                &mut r,
+                ",",
+                &NumberFormat::default(),
+                2,
+                batch_size,
+            )
+            .unwrap();
+            assert_eq!(t.columns, whole.columns);
+            assert_eq!(t.records, whole.records);
+            let possible = t.get_possible(&[Selection {
+                column: String::from("name"),
+                value: String::from("ai"),
+            }]);
+            assert_eq!(possible, vec![&t.records[2]]);
+        }
+
+        // errors name the line the record starts on
+        let text = "\nname,note\nni,ok\nsi,\"open\nai,x\n";
+        let error = load_csv_text("notes", text, ",", &NumberFormat::default(), 2)
+            .err()
+            .unwrap();
+        assert_eq!(error.message, "notes line 4: unterminated quoted field");
+        let mut r = io::Cursor::new(text);
+        let error = load_csv_batches("notes", &mut r, ",", &NumberFormat::default(), 2, 1)
+            .err()
+            .unwrap();
+        assert_eq!(error.message, "notes line 4: unterminated quoted field");
+        let mut records = CsvRecords::new(
+            "notes",
+            io::Cursor::new(text),
+            ",",
+            &NumberFormat::default(),
+        )
+        .unwrap();
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(
+            records.next().unwrap().unwrap_err().message,
+            "notes line 4: unterminated quoted field"
+        );
+    }
+
+    #[test]
+    fn read_csv_records() {
+        let r = io::Cursor::new("name,note\r\nni,\"two\nlines\"\n\nsi,5\"\nai,\"a \"\"b\"\"\"");
+        let mut records = CsvRecords::new("notes", r, ",", &NumberFormat::default()).unwrap();
+        assert_eq!(records.get_columns(), &vec!["name", "note"]);
+        assert_eq!(
+            records.next().unwrap().unwrap(),
+            vec![
+                DataType::from_string("ni"),
+                DataType::from_string("two\nlines")
+            ]
+        );
+        assert_eq!(
+            records.next().unwrap().unwrap(),
+            vec![DataType::from_string("si"), DataType::from_string("5\"")]
+        );
+        assert_eq!(
+            records.next().unwrap().unwrap(),
+            vec![
+                DataType::from_string("ai"),
+                DataType::from_string("a \"b\"")
+            ]
+        );
+        assert!(records.next().is_none());
+        assert!(CsvRecords::new(
+            "empty",
+            io::Cursor::new("\n"),
+            ",",
+            &NumberFormat::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn load_gzipped_file() {
+        let path = std::env::temp_dir().join("load-test-people.csv.gz");
+        // gzip of "name,age\nni,35\n"
+        let compressed = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0xcb, 0x4b, 0xcc, 0x4d,
+            0xd5, 0x49, 0x4c, 0x4f, 0xe5, 0xca, 0xcb, 0xd4, 0x31, 0x36, 0xe5, 0x02, 0x00, 0x45,
+            0xe5, 0x49, 0x0e, 0x0f, 0x00, 0x00, 0x00,
+        ];
+        fs::write(&path, compressed).unwrap();
+
+        let format = InputFormat::from_path(&path).unwrap();
+        assert_eq!(format, InputFormat::Csv);
+        let t = load_file(path.to_str().unwrap(), format, &NumberFormat::default()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(t.columns, vec!["name", "age"]);
+        assert_eq!(
+            t.records,
+            vec![vec![DataType::from_string("ni"), DataType::Int(35)]]
+        );
+    }
+
+    #[test]
+    fn load_fixed_width_lines() {
+        let layout = parse_layout(
+            "# name, start, width, type\n\
+             id 1 4 int\n\
+             name 5 6\n\
+             amount 11 7 decimal\n",
+        )
+        .unwrap();
+        assert_eq!(layout[1].field_type, FieldType::Auto);
+
+        let mut r = io::Cursor::new("0001ni      12.50\r\n  02åsa    -3\n0003si\n");
+        let t = load_fixed_width("extract", &mut r, &layout, &NumberFormat::default()).unwrap();
+
+        assert_eq!(t.columns, vec!["id", "name", "amount"]);
+        assert_eq!(
+            t.records,
+            vec![
+                vec![
+                    DataType::Int(1),
+                    DataType::String("ni".into()),
+                    DataType::Decimal(1250, 2)
+                ],
+                vec![
+                    DataType::Int(2),
+                    DataType::String("åsa".into()),
+                    DataType::Int(-3)
+                ],
+                vec![
+                    DataType::Int(3),
+                    DataType::String("si".into()),
+                    DataType::Null
+                ],
+            ]
+        );
+
+        let mut r = io::Cursor::new("0001ni      12.50\n00x2si\n");
+        match load_fixed_width("extract", &mut r, &layout, &NumberFormat::default()) {
+            Ok(_) => panic!("expected a type error"),
+            Err(e) => assert_eq!(e.message, "extract line 2: id is not an int: 00x2"),
+        }
+        assert!(parse_layout("id 0 4").is_err());
+        assert!(parse_layout("id 1 4 date").is_err());
+    }
+
+    #[test]
+    fn load_ndjson_with_nested_objects() {
+        let content = r#"{"id": 1, "user": {"name": "ni", "geo": {"country": "swe"}}, "ok": true}
+
+{"id": 2.50, "user": {"name": "si"}, "tags": ["a", "b"], "ok": null, "size": 1e3}
+"#;
+        let t = load_json("events", content).unwrap();
+
+        assert_eq!(
+            t.columns,
+            vec!["id", "user.name", "user.geo.country", "ok", "tags", "size"]
+        );
+        assert_eq!(
+            t.records[0],
+            vec![
+                DataType::Int(1),
+                DataType::String("ni".into()),
+                DataType::String("swe".into()),
+                DataType::Bool(true),
+                DataType::Null,
+                DataType::Null,
+            ]
+        );
+        assert_eq!(t.records[1][0], DataType::Decimal(250, 2));
+        assert_eq!(t.records[1][2], DataType::Null);
+        assert_eq!(t.records[1][4], DataType::String(r#"["a","b"]"#.into()));
+        assert_eq!(t.records[1][5], DataType::Int(1000));
+        assert_eq!(t.get_values("user.geo.country"), vec!["swe"]);
+    }
+
+    #[test]
+    fn load_json_array() {
+        let t = load_json("events", r#"[{"a": -3}, {"b": "x"}, {"b": "2023-05-31"}]"#).unwrap();
+        assert_eq!(t.columns, vec!["a", "b"]);
+        assert_eq!(t.records[0], vec![DataType::Int(-3), DataType::Null]);
+        assert_eq!(t.records[2][1], DataType::Date(19_508));
+
+        match load_json("events", "{\"a\": 1}\n{\"a\": \n") {
+            Ok(_) => panic!("expected a parse error"),
+            Err(e) => assert!(e.message.starts_with("events line 2:")),
+        }
+        assert!(load_json("events", "[1, 2]").is_err());
+        let e = load_json("events", r#"[{"a": 1}, {"a": 1e999}]"#)
+            .err()
+            .unwrap();
+        assert_eq!(e.message, "events record 2: a is out of range: 1e999");
+    }
+}
@@ -1,56 +1,63 @@
-mod ast;
-mod model;
-mod join;
-
 use std::env;
 use std::fs;
 use std::io;
-use std::io::BufRead;
 
-#[derive(Debug)]
-struct ReadColumnError {
-    message: String,
-}
+use data_types::ast;
+use data_types::bookmark;
+use data_types::calendar;
+use data_types::decimal;
+use data_types::editor;
+use data_types::format;
+use data_types::http;
+use data_types::load;
+use data_types::model;
+use data_types::repl;
+use data_types::server;
+use data_types::snapshot;
+use data_types::stream;
 
-fn read_csv_line(
-    reader: &mut io::BufReader<fs::File>,
-    delim: &str,
-) -> Result<Vec<String>, ReadColumnError> {
-    let mut buf = String::new();
-    let some = reader
-        .read_line(&mut buf)
-        .expect("read columns should work");
-    if some == 0 {
-        Result::Err(ReadColumnError {
-            message: String::from("file empty"),
-        })
-    } else {
-        let cols: Vec<String> = parse_csv_line(&buf, delim, None);
-        Result::Ok(cols)
-    }
+/// a group by aggregation computed while reading the input,
+/// without loading it into a model
+#[derive(Debug)]
+struct Stream {
+    group_by: Vec<String>,
+    /// `--where`, keeps the records for which the expression is
+    /// true, such as `gt(age, 30)`
+    filter: Option<String>,
+    aggregates: Vec<String>,
+    max_groups: usize,
 }
 
-fn parse_csv_line(line: &str, delim: &str, max_cols: Option<usize>) -> Vec<String> {
-    let x = line.split(delim).map(|s| s.trim_end());
-    match max_cols {
-        None => x.map(String::from).collect(),
-        Some(max) => x.take(max).map(String::from).collect(),
-    }
+/// commands to run without entering the interactive loop
+#[derive(Debug)]
+enum Batch {
+    Exec(String),
+    Script(String),
 }
 
 #[derive(Debug)]
 struct Commands {
     infile: String,
     select: Vec<model::Selection>,
+    serve: bool,
+    port: u16,
+    http_port: Option<u16>,
+    batch: Vec<Batch>,
+    format: format::Format,
+    input_format: Option<load::InputFormat>,
+    snapshot: Option<String>,
+    layout: Option<String>,
+    calendar: bool,
+    numbers: decimal::NumberFormat,
+    stream: Option<Stream>,
 }
 
 impl Commands {
     fn from_args() -> Commands {
         let mut commands = Commands::new();
         let args: Vec<String> = env::args().skip(1).collect();
-        match commands.parse(&args) {
-            Err(err) => panic!("failed to parse command line args: {}", err),
-            _ => (),
+        if let Err(err) = commands.parse(&args) {
+            panic!("failed to parse command line args: {}", err);
         }
         commands
     }
@@ -59,10 +66,21 @@ impl Commands {
         Commands {
             infile: String::new(),
             select: Vec::new(),
+            serve: false,
+            port: 8080,
+            http_port: None,
+            batch: Vec::new(),
+            format: format::Format::Table,
+            input_format: None,
+            snapshot: None,
+            layout: None,
+            calendar: false,
+            numbers: decimal::NumberFormat::default(),
+            stream: None,
         }
     }
 
-    fn parse(&mut self, args: &Vec<String>) -> Result<(), &str> {
+    fn parse(&mut self, args: &[String]) -> Result<(), &str> {
         let mut it = args.iter();
 
         loop {
@@ -75,6 +93,85 @@ impl Commands {
                         Some(s) => String::from(s),
                     };
                 }
+                Some(s) if s == "serve" => {
+                    self.serve = true;
+                }
+                Some(s) if s == "--port" => {
+                    self.port = match it.next().map(|p| p.parse()) {
+                        Some(Ok(port)) => port,
+                        _ => return Err("ERROR: --port expects a port number"),
+                    };
+                }
+                Some(s) if s == "--http-port" => {
+                    self.http_port = match it.next().map(|p| p.parse()) {
+                        Some(Ok(port)) => Some(port),
+                        _ => return Err("ERROR: --http-port expects a port number"),
+                    };
+                }
+                Some(s) if s == "--exec" => match it.next() {
+                    Some(cmds) => self.batch.push(Batch::Exec(String::from(cmds))),
+                    None => return Err("ERROR: --exec expects commands"),
+                },
+                Some(s) if s == "--script" => match it.next() {
+                    Some(path) => self.batch.push(Batch::Script(String::from(path))),
+                    None => return Err("ERROR: --script expects a file"),
+                },
+                Some(s) if s == "--snapshot" => match it.next() {
+                    Some(path) => self.snapshot = Some(String::from(path)),
+                    None => return Err("ERROR: --snapshot expects a file"),
+                },
+                Some(s) if s == "--layout" => match it.next() {
+                    Some(path) => self.layout = Some(String::from(path)),
+                    None => return Err("ERROR: --layout expects a file"),
+                },
+                Some(s) if s == "--calendar" => {
+                    self.calendar = true;
+                }
+                Some(s) if s == "--decimal-comma" => {
+                    self.numbers = decimal::NumberFormat::decimal_comma();
+                }
+                Some(s) if s == "--thousands" => {
+                    let mut chars = it.next().map(|c| c.chars());
+                    self.numbers.thousands = match chars.as_mut().map(|c| (c.next(), c.next())) {
+                        Some((Some(c), None)) => Some(c),
+                        _ => return Err("ERROR: --thousands expects a separator character"),
+                    };
+                }
+                Some(s) if s == "--stream" => {
+                    self.stream_mut();
+                }
+                Some(s) if s == "--group-by" => match it.next() {
+                    Some(cols) => {
+                        self.stream_mut().group_by = cols.split(',').map(String::from).collect()
+                    }
+                    None => return Err("ERROR: --group-by expects columns"),
+                },
+                Some(s) if s == "--where" => match it.next() {
+                    Some(expr) => self.stream_mut().filter = Some(String::from(expr)),
+                    None => return Err("ERROR: --where expects an expression"),
+                },
+                Some(s) if s == "--agg" => match it.next() {
+                    Some(expr) => self.stream_mut().aggregates.push(String::from(expr)),
+                    None => return Err("ERROR: --agg expects an expression"),
+                },
+                Some(s) if s == "--max-groups" => {
+                    self.stream_mut().max_groups = match it.next().map(|n| n.parse()) {
+                        Some(Ok(n)) => n,
+                        _ => return Err("ERROR: --max-groups expects a number"),
+                    };
+                }
+                Some(s) if s == "--format" => {
+                    self.format = match it.next().and_then(|f| format::Format::from_string(f)) {
+                        Some(f) => f,
+                        None => return Err("ERROR: --format expects table, csv, json, ndjson or markdown"),
+                    };
+                }
+                Some(s) if s == "--input-format" => {
+                    self.input_format = match it.next().and_then(|f| load::InputFormat::from_string(f)) {
+                        Some(f) => Some(f),
+                        None => return Err("ERROR: --input-format expects csv, json or ndjson"),
+                    };
+                }
                 Some(s) if s == "--select" => {
                     let stmt = match it.next() {
                         None => break,
@@ -95,65 +192,209 @@ impl Commands {
             }
         }
 
-        if self.infile == "" {
-            return Err("ERROR: no in file specified");
+        if self.infile.is_empty() && self.snapshot.is_none() {
+            return Err("ERROR: no in file or snapshot specified");
         }
-        return Ok(());
+        if self.stream.is_some() && self.infile.is_empty() {
+            return Err("ERROR: --stream expects an in file");
+        }
+        Ok(())
+    }
+
+    /// the stream options, any of them turns on streaming
+    fn stream_mut(&mut self) -> &mut Stream {
+        self.stream.get_or_insert_with(|| Stream {
+            group_by: vec![],
+            filter: None,
+            aggregates: vec![],
+            max_groups: 1_000_000,
+        })
     }
 }
 
+/// reads the csv input record by record into the aggregation
+/// and prints a row per group
+fn run_stream(commands: &Commands, options: &Stream) -> Result<(), String> {
+    let infile = &commands.infile;
+    let parse = |s: &str| {
+        ast::Expression::from_string(s).map_err(|e| format!("failed to parse {}: {}", s, e.message))
+    };
+    let filter = options.filter.as_deref().map(parse).transpose()?;
+    let mut exprs = options
+        .aggregates
+        .iter()
+        .map(|s| parse(s))
+        .collect::<Result<Vec<_>, _>>()?;
+    if exprs.is_empty() {
+        exprs.push(ast::Expression::Count);
+    }
+
+    let r = load::open_reader(infile).map_err(|e| e.message)?;
+    let records =
+        load::CsvRecords::new(infile, r, ",", &commands.numbers).map_err(|e| e.message)?;
+    let columns = records.get_columns().clone();
+    let mut aggregation = stream::Aggregation::new(
+        &columns,
+        &options.group_by,
+        filter,
+        exprs.clone(),
+        options.max_groups,
+    )
+    .map_err(|e| e.message)?;
+    let mut count = 0;
+    for record in records {
+        let record = record.map_err(|e| e.message)?;
+        aggregation.push(&record).map_err(|e| e.message)?;
+        count += 1;
+    }
+    eprintln!("Streamed {} rows", count);
+
+    let mut header = options.group_by.clone();
+    header.extend(exprs.iter().map(|e| e.to_string()));
+    let rows: Vec<Vec<model::DataType>> = aggregation
+        .finish()
+        .map_err(|e| e.message)?
+        .into_iter()
+        .map(|(mut key, values)| {
+            key.extend(values);
+            key
+        })
+        .collect();
+    print!("{}", commands.format.format(&header, &rows));
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
     let commands = Commands::from_args();
-    println!("commands: {:#?}", &commands);
-    let f = match fs::File::open(&commands.infile) {
-        Ok(f) => f,
-        Err(e) => {
-            panic!("failed to open file {}: {}", &commands.infile, e);
+
+    if let Some(options) = &commands.stream {
+        if let Err(e) = run_stream(&commands, options) {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
-    };
-    let mut r = io::BufReader::new(f);
-    let delim = ",";
+        return Ok(());
+    }
 
     let start = std::time::Instant::now();
-    println!("Loading files...");
+    eprintln!("Loading files...");
 
-    let columns = read_csv_line(&mut r, delim).expect("to have columns");
-    let num_columns = columns.len();
-    let mut t = model::Table::new(&commands.infile, columns);
+    // a snapshot is written from the csv file when both are given,
+    // and loaded instead of it when only the snapshot is given
+    let m = if commands.infile.is_empty() {
+        let path = commands.snapshot.as_deref().unwrap_or_default();
+        match snapshot::load(std::path::Path::new(path)) {
+            Ok(m) => m,
+            Err(e) => panic!("failed to load snapshot: {}", e),
+        }
+    } else {
+        let mut m = model::Model::new();
+        // a layout makes the input fixed width, otherwise --input-format
+        // or else the extension decides, csv when neither does
+        let input_format = commands
+            .input_format
+            .clone()
+            .or_else(|| load::InputFormat::from_path(std::path::Path::new(&commands.infile)));
+        let format = match (&commands.layout, input_format) {
+            (Some(path), _) => {
+                let layout = fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|text| load::parse_layout(&text).map_err(|e| e.message));
+                match layout {
+                    Ok(layout) => load::InputFormat::FixedWidth(layout),
+                    Err(e) => panic!("failed to read layout {}: {}", path, e),
+                }
+            }
+            (None, Some(format)) => format,
+            (None, None) => load::InputFormat::Csv,
+        };
+        match load::load_file(&commands.infile, format, &commands.numbers) {
+            Ok(t) => {
+                let name = t.name.clone();
+                m.add_table(t);
+                // a calendar table per date column, associated by its dates
+                if commands.calendar {
+                    calendar::add_calendars(&mut m, &name);
+                }
+            }
+            Err(e) => panic!("failed to load {}: {}", commands.infile, e),
+        }
+        if let Some(path) = &commands.snapshot {
+            if let Err(e) = snapshot::save(&m, std::path::Path::new(path)) {
+                panic!("failed to save snapshot: {}", e);
+            }
+        }
+        m
+    };
 
-    let mut buf = String::new();
-    loop {
-        match r.read_line(&mut buf) {
-            Ok(0) => break,
-            Err(e) => {
-                println!("failed to read line {}", e);
-                break;
+    let elapsed = start.elapsed();
+    let rows: usize = m.get_tables().iter().map(|t| t.records.len()).sum();
+    eprintln!(
+        "Files loaded. Time elapsed: {} ms, {} rows ({:.0} rows/sec)",
+        elapsed.as_millis(),
+        rows,
+        rows as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+
+    if commands.serve || commands.http_port.is_some() {
+        let m = std::sync::Arc::new(m);
+
+        // the http api runs next to the websocket server with `serve`,
+        // or on its own without it
+        if let Some(port) = commands.http_port {
+            let http_server = http::HttpServer::bind(&format!("127.0.0.1:{}", port))?;
+            println!("Serving http queries on {}", http_server.local_addr()?);
+            if !commands.serve {
+                return http_server.run(m);
             }
-            _ => (),
+            let m = m.clone();
+            std::thread::spawn(move || http_server.run(m));
         }
-        let fields = parse_csv_line(&buf, delim, Some(num_columns));
-        let dts: Vec<model::DataType> = fields
-            .iter()
-            .map(|f| model::DataType::from_string(f.as_str()))
-            .collect();
-        t.insert(dts);
-        buf.clear();
+
+        let server = server::Server::bind(&format!("127.0.0.1:{}", commands.port))?;
+        println!("Serving websocket connections on {}", server.local_addr()?);
+        return server.run(m);
     }
 
-    println!(
-        "Files loaded. Time elapsed: {} ms",
-        start.elapsed().as_millis()
-    );
+    let t = match m.get_tables().first() {
+        Some(t) => t,
+        None => panic!("no tables loaded"),
+    };
+    let bookmarks = match bookmark::Bookmarks::load(&bookmark::Bookmarks::path_for(&t.name)) {
+        Ok(b) => b,
+        Err(e) => panic!("failed to load bookmarks: {}", e),
+    };
 
-    let start_fetch = std::time::Instant::now();
-    println!("Start timer...");
+    if !commands.batch.is_empty() {
+        let mut repl = repl::Repl::new(m.new_data_context(), bookmarks);
+        repl.set_format(commands.format);
+        for batch in &commands.batch {
+            let result = match batch {
+                Batch::Exec(cmds) => repl.run_batch(cmds),
+                Batch::Script(path) => match fs::read_to_string(path) {
+                    Ok(script) => repl.run_batch(&script),
+                    Err(e) => {
+                        eprintln!("failed to read script {}: {}", path, e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            if let Err(e) = result {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
 
-    let mut ctx = t.new_context();
+    let mut ctx = m.new_data_context();
     ctx.observe(|ctx| {
         println!(
             "sum age: {}",
-            ctx.sum(String::from("age"))
-                .unwrap_or(model::DataType::Int(0))
+            match ctx.sum(String::from("age")) {
+                Some(Ok(sum)) => sum.to_string(),
+                Some(Err(e)) => e.to_string(),
+                None => String::from("0"),
+            }
         );
     });
 
@@ -173,54 +414,20 @@ fn main() -> io::Result<()> {
         );
     });
 
-    loop {
-        println!("Provide a command");
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer)?;
-
-        let tokens: Vec<&str> = buffer.split(" ").collect();
-
-        if tokens.len() < 2 {
-            println!("Provide a valid command");
-            continue;
-        }
-
-        let sel: Vec<model::Selection>;
+    let mut repl = repl::Repl::new(ctx, bookmarks);
+    repl.set_format(commands.format);
+    let mut editor = editor::LineEditor::new(editor::LineEditor::default_history_path());
 
-        match tokens[0] {
-            "select" | "deselect" => {
-                if !tokens[1].contains("=") {
-                    println!("Invalid select argument");
-                    continue;
-                }
-
-                let parts: Vec<&str> = tokens[1].split("=").collect();
-
-                sel = parts[1]
-                    .split(",")
-                    .map(|s| s.trim_end())
-                    .map(|val| model::Selection {
-                        column: String::from(parts[0]),
-                        value: String::from(val),
-                    })
-                    .collect();
-            }
-            _ => {
-                println!("Unrecognized command");
-                continue;
-            }
-        }
-
-        println!("{}: {:#?}", tokens[0], sel);
+    println!("Provide a command");
+    loop {
+        let line = match editor.read_line("> ", &repl)? {
+            Some(line) => line,
+            None => return Ok(()),
+        };
+        editor.add_history(&line);
 
-        if tokens[0] == "select" {
-            for s in sel {
-                ctx.select(&s);
-            }
-        } else {
-            for s in sel {
-                ctx.deselect(&s);
-            }
+        if let Err(e) = repl.execute(&line) {
+            println!("{}", e);
         }
     }
 }
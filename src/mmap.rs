@@ -0,0 +1,180 @@
+use std::fs;
+use std::io;
+use std::ops::Deref;
+
+/// the mmap bindings, declared by hand as the crate has no
+/// dependencies, so they are limited to the 64-bit targets where
+/// `PROT_READ` is 1, `MAP_PRIVATE` is 2 and `off_t` is 64 bits
+#[cfg(all(
+    target_pointer_width = "64",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly",
+    )
+))]
+mod sys {
+    use std::fs;
+    use std::io;
+    use std::os::raw::{c_int, c_void};
+    use std::os::unix::io::AsRawFd;
+
+    const PROT_READ: c_int = 1;
+    const MAP_PRIVATE: c_int = 2;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    /// maps len bytes of the file, none when it can't be mapped
+    pub fn map(file: &fs::File, len: usize) -> io::Result<Option<*const u8>> {
+        // SAFETY: a private read-only mapping of a file we hold open,
+        // it is unmapped exactly once in drop
+        let ptr = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                PROT_READ,
+                MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr as isize == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(ptr as *const u8))
+    }
+
+    /// # Safety
+    ///
+    /// ptr and len must come from a successful `map`
+    pub unsafe fn unmap(ptr: *const u8, len: usize) {
+        munmap(ptr as *mut c_void, len);
+    }
+}
+
+/// elsewhere files are read into a buffer
+#[cfg(not(all(
+    target_pointer_width = "64",
+    any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "dragonfly",
+    )
+)))]
+mod sys {
+    use std::fs;
+    use std::io;
+
+    pub fn map(_: &fs::File, _: usize) -> io::Result<Option<*const u8>> {
+        Ok(None)
+    }
+
+    /// # Safety
+    ///
+    /// nothing is ever mapped, so this is never called
+    pub unsafe fn unmap(_: *const u8, _: usize) {}
+}
+
+/// a read-only view of a whole file, mapped into memory where the
+/// platform allows it and read into a buffer otherwise
+///
+/// the file must not be modified while it is mapped
+pub struct Mmap {
+    ptr: *const u8,
+    len: usize,
+    buffer: Vec<u8>,
+}
+
+impl Mmap {
+    pub fn open(file: &fs::File) -> io::Result<Mmap> {
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Mmap::read(file);
+        }
+        match sys::map(file, len)? {
+            Some(ptr) => Ok(Mmap {
+                ptr,
+                len,
+                buffer: vec![],
+            }),
+            None => Mmap::read(file),
+        }
+    }
+
+    fn read(mut file: &fs::File) -> io::Result<Mmap> {
+        let mut buffer = vec![];
+        io::Read::read_to_end(&mut file, &mut buffer)?;
+        Ok(Mmap::from(buffer))
+    }
+}
+
+impl From<Vec<u8>> for Mmap {
+    /// wraps bytes that are already in memory
+    fn from(buffer: Vec<u8>) -> Mmap {
+        Mmap {
+            ptr: std::ptr::null(),
+            len: 0,
+            buffer,
+        }
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.ptr.is_null() {
+            &self.buffer
+        } else {
+            // SAFETY: the mapping is valid for len bytes until drop
+            unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            // SAFETY: ptr and len come from a successful map
+            unsafe { sys::unmap(self.ptr, self.len) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn map_file() {
+        let path = std::env::temp_dir().join("mmap-test.csv");
+        fs::write(&path, "name,age\nni,35\n").unwrap();
+        let map = Mmap::open(&fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(&map[..], b"name,age\nni,35\n");
+
+        fs::write(&path, "").unwrap();
+        let map = Mmap::open(&fs::File::open(&path).unwrap()).unwrap();
+        assert!(map.is_empty());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -2,148 +2,262 @@ use std::cmp::Ordering;
 use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::num::ParseIntError;
+use std::sync::Arc;
 use std::vec;
 
+use crate::ast;
+use crate::date;
+use crate::decimal;
+use crate::decimal::{ArithmeticError, NumberFormat, Rounding};
+use crate::ast::{EvalError, Expression, GroupRow, Sort, SortKey};
+
 pub type Column = String;
 pub type Columns = Vec<Column>;
 pub type Value = String;
 pub type Record = Vec<DataType>;
 
-#[derive(Eq, Hash, PartialEq, Debug, Clone, Ord, PartialOrd)]
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
 pub enum DataType {
-    String(String),
+    /// shared, so that equal strings loaded together can use one allocation
+    String(Arc<str>),
     Decimal(i64, u8),
     Int(i64),
+    Bool(bool),
+    /// days since 1970-01-01
+    Date(i32),
+    /// seconds since 1970-01-01 00:00:00, without a time zone
+    Timestamp(i64),
+    Null,
 }
 
 impl DataType {
+    /// an int or decimal when the text is a number written
+    /// the usual way, a string otherwise
     pub fn from_string(s: &str) -> DataType {
-        let int_result: Result<i64, _> = s.parse();
-        match int_result {
-            Ok(i) => return DataType::Int(i),
-            _ => (),
-        }
-
-        let float_result: Result<f64, _> = s.parse();
-        match float_result {
-            Ok(_f) => {
-                let parts: Vec<&str> = s.split(".").collect();
-                let decimal_points = parts[1].len();
-                let value = String::from(parts[0]) + parts[1];
-                let num_result: Result<i64, ParseIntError> = value.parse();
-                return match num_result {
-                    Ok(num) => DataType::Decimal(num, decimal_points as u8),
-                    _ => DataType::String(String::from(s)),
-                };
-            }
-            _ => DataType::String(String::from(s)),
+        DataType::parse(s, &NumberFormat::default())
+    }
+
+    /// an int or decimal when the text is a number in the given
+    /// format, a date or timestamp when it is one, a string
+    /// otherwise, including for numbers that do not fit an i64
+    pub fn parse(s: &str, format: &NumberFormat) -> DataType {
+        let number =
+            decimal::parse_number(s, format).and_then(|(v, p)| Some((i64::try_from(v).ok()?, p)));
+        match number {
+            Some((v, 0)) => DataType::Int(v),
+            Some((v, p)) => DataType::Decimal(v, p),
+            None => DataType::parse_date(s).unwrap_or_else(|| DataType::String(s.into())),
         }
     }
 
-    fn cmp(x: &DataType, y: &DataType) -> Ordering {
-        let t = (x, y);
+    /// a date for `YYYY-MM-DD` text, a timestamp for
+    /// `YYYY-MM-DD HH:MM[:SS]` text
+    pub fn parse_date(s: &str) -> Option<DataType> {
+        if let Some(days) = date::parse_date(s) {
+            return i32::try_from(days).ok().map(DataType::Date);
+        }
+        date::parse_timestamp(s).map(DataType::Timestamp)
+    }
 
-        return match t {
-            (DataType::String(s1), DataType::String(s2)) => s1.cmp(s2),
-            (DataType::String(_), _) => Ordering::Less,
-            (_, DataType::String(_)) => Ordering::Greater,
-            (DataType::Int(i1), DataType::Int(i2)) => i1.cmp(i2),
-            (DataType::Decimal(num1, p1), DataType::Decimal(num2, p2)) => {
-                Self::precision_mul(*num1, *p2).cmp(&Self::precision_mul(*num2, *p1))
-            }
-            (DataType::Decimal(num1, p), DataType::Int(num2)) => {
-                num1.cmp(&Self::precision_mul(*num2, *p))
-            }
-            (DataType::Int(num1), DataType::Decimal(num2, p)) => {
-                Self::precision_mul(*num1, *p).cmp(num2)
-            }
-        };
+    /// the seconds since 1970-01-01 of a date or timestamp
+    pub fn as_seconds(&self) -> Option<i64> {
+        match self {
+            DataType::Date(days) => Some(*days as i64 * date::SECONDS_PER_DAY),
+            DataType::Timestamp(seconds) => Some(*seconds),
+            _ => None,
+        }
     }
 
-    fn add(d1: i64, d2: i64, p1: u8, p2: u8) -> DataType {
-        if p1 == 0 && p2 == 0 {
-            return DataType::Int(d1 + d2);
+    /// whether the value is missing, null or the empty string
+    pub fn is_null(&self) -> bool {
+        match self {
+            DataType::Null => true,
+            DataType::String(s) => s.is_empty(),
+            _ => false,
         }
+    }
 
-        if p1 == p2 {
-            return DataType::Decimal(d1 + d2, p1);
-        } else if p1 > p2 {
-            let diff = p1 - p2;
-            return DataType::Decimal(d1 + Self::precision_mul(d2, diff), p1);
-        } else {
-            let diff = p2 - p1;
-            return DataType::Decimal(Self::precision_mul(d1, diff) + d2, p2);
+    /// whether the value counts as true in a condition, null,
+    /// false, zero and the empty string do not
+    pub fn is_true(&self) -> bool {
+        match self {
+            DataType::Bool(b) => *b,
+            DataType::Int(i) => *i != 0,
+            DataType::Decimal(v, _) => *v != 0,
+            DataType::String(s) => !s.is_empty(),
+            DataType::Date(_) | DataType::Timestamp(_) => true,
+            DataType::Null => false,
         }
     }
 
-    fn sum(acc: DataType, next: DataType) -> DataType {
-        let num1;
-        let p1;
-        let num2;
-        let p2;
-        match acc {
-            DataType::Int(n) => {
-                num1 = n;
-                p1 = 0;
-            }
-            DataType::Decimal(n, p) => {
-                num1 = n;
-                p1 = p;
-            }
-            DataType::String(_) => {
-                num1 = 0;
-                p1 = 0;
-            }
+    /// the position of the type in the order of values
+    fn rank(&self) -> u8 {
+        match self {
+            DataType::Null => 0,
+            DataType::Bool(_) => 1,
+            DataType::Int(_) | DataType::Decimal(_, _) => 2,
+            DataType::Date(_) | DataType::Timestamp(_) => 3,
+            DataType::String(_) => 4,
         }
-        match next {
-            DataType::Int(n) => {
-                num2 = n;
-                p2 = 0;
-            }
-            DataType::Decimal(n, p) => {
-                num2 = n;
-                p2 = p;
-            }
-            DataType::String(_) => {
-                num2 = 0;
-                p2 = 0;
-            }
+    }
+
+    /// compares like `Ord`, with strings ordered by the collation
+    pub fn collate(&self, other: &DataType, collation: Collation) -> Ordering {
+        match (self, other, collation) {
+            (DataType::String(a), DataType::String(b), Collation::CaseInsensitive) => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.chars().flat_map(char::to_lowercase))
+                .then_with(|| a.cmp(b)),
+            _ => self.cmp(other),
         }
+    }
+
+    /// the scaled integer and scale of a number, anything
+    /// else counts as zero
+    fn as_decimal(&self) -> (i128, u8) {
+        match self {
+            DataType::Int(n) => (*n as i128, 0),
+            DataType::Decimal(n, p) => (*n as i128, *p),
+            _ => (0, 0),
+        }
+    }
+
+    /// narrows a result back to a value, an int when the scale is zero
+    fn from_decimal(value: i128, scale: u8, op: &str) -> Result<DataType, ArithmeticError> {
+        let value = i64::try_from(value).map_err(|_| decimal::overflow(op))?;
+        match scale {
+            0 => Ok(DataType::Int(value)),
+            _ => Ok(DataType::Decimal(value, scale)),
+        }
+    }
+
+    /// adds two values, strings, booleans and nulls count as zero
+    pub fn sum(acc: DataType, next: DataType) -> Result<DataType, ArithmeticError> {
+        acc.checked_add(&next)
+    }
+
+    pub fn checked_add(&self, other: &DataType) -> Result<DataType, ArithmeticError> {
+        let ((a, pa), (b, pb)) = (self.as_decimal(), other.as_decimal());
+        let (sum, scale) = decimal::add(a, pa, b, pb).ok_or_else(|| decimal::overflow("add"))?;
+        Self::from_decimal(sum, scale, "add")
+    }
+
+    pub fn checked_sub(&self, other: &DataType) -> Result<DataType, ArithmeticError> {
+        let ((a, pa), (b, pb)) = (self.as_decimal(), other.as_decimal());
+        let (diff, scale) = decimal::add(a, pa, -b, pb).ok_or_else(|| decimal::overflow("sub"))?;
+        Self::from_decimal(diff, scale, "sub")
+    }
 
-        Self::add(num1, num2, p1, p2)
+    /// the exact product, its scale is the sum of the scales
+    pub fn checked_mul(&self, other: &DataType) -> Result<DataType, ArithmeticError> {
+        let ((a, pa), (b, pb)) = (self.as_decimal(), other.as_decimal());
+        let (product, scale) =
+            decimal::mul(a, pa, b, pb).ok_or_else(|| decimal::overflow("mul"))?;
+        Self::from_decimal(product, scale, "mul")
     }
 
-    fn precision_mul(num: i64, prec: u8) -> i64 {
-        if prec == 0 {
-            return num;
+    /// the quotient rounded to `scale` decimals
+    pub fn checked_div(
+        &self,
+        other: &DataType,
+        scale: u8,
+        rounding: Rounding,
+    ) -> Result<DataType, ArithmeticError> {
+        let ((a, pa), (b, pb)) = (self.as_decimal(), other.as_decimal());
+        if b == 0 {
+            return Err(ArithmeticError {
+                message: String::from("division by zero"),
+            });
         }
-        let mut res = num;
-        for _i in 0..prec {
-            res = res * 10;
+        let quotient =
+            decimal::div(a, pa, b, pb, scale, rounding).ok_or_else(|| decimal::overflow("div"))?;
+        Self::from_decimal(quotient, scale, "div")
+    }
+
+    /// the value with `scale` decimals, rounding when digits are dropped
+    pub fn round(&self, scale: u8, rounding: Rounding) -> Result<DataType, ArithmeticError> {
+        let (v, p) = self.as_decimal();
+        let rounded =
+            decimal::rescale(v, p, scale, rounding).ok_or_else(|| decimal::overflow("round"))?;
+        Self::from_decimal(rounded, scale, "round")
+    }
+}
+
+/// nulls first, then booleans, then numbers by value whether they
+/// are ints or decimals, then dates and timestamps by time, then strings
+///
+/// numbers that are equal in value but not in representation, such
+/// as `5` and `5.0`, are ordered by scale so that the order agrees
+/// with `Eq`, a date is before a timestamp at its midnight
+impl Ord for DataType {
+    fn cmp(&self, other: &DataType) -> Ordering {
+        match (self, other) {
+            (DataType::String(a), DataType::String(b)) => a.cmp(b),
+            (DataType::Bool(a), DataType::Bool(b)) => a.cmp(b),
+            (DataType::Int(a), DataType::Int(b)) => a.cmp(b),
+            (
+                DataType::Int(_) | DataType::Decimal(_, _),
+                DataType::Int(_) | DataType::Decimal(_, _),
+            ) => {
+                let ((a, pa), (b, pb)) = (self.as_decimal(), other.as_decimal());
+                decimal::compare(a, pa, b, pb)
+                    .then(pa.cmp(&pb))
+                    .then_with(|| {
+                        let is_decimal = |v: &DataType| matches!(v, DataType::Decimal(_, _));
+                        is_decimal(self).cmp(&is_decimal(other))
+                    })
+            }
+            (
+                DataType::Date(_) | DataType::Timestamp(_),
+                DataType::Date(_) | DataType::Timestamp(_),
+            ) => self.as_seconds().cmp(&other.as_seconds()).then_with(|| {
+                let is_timestamp = |v: &DataType| matches!(v, DataType::Timestamp(_));
+                is_timestamp(self).cmp(&is_timestamp(other))
+            }),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for DataType {
+    fn partial_cmp(&self, other: &DataType) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub const COLLATIONS: [&str; 2] = ["binary", "nocase"];
+
+/// how strings are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Collation {
+    /// by unicode code point
+    #[default]
+    Binary,
+    /// ignoring case, ties broken by code point
+    CaseInsensitive,
+}
+
+impl Collation {
+    pub fn from_string(s: &str) -> Option<Collation> {
+        match s {
+            "binary" => Some(Collation::Binary),
+            "nocase" => Some(Collation::CaseInsensitive),
+            _ => None,
         }
-        res
     }
 }
 
 impl std::fmt::Display for DataType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
-            DataType::Decimal(v, p) => {
-                if *p == 0 {
-                    write!(f, "{}", v)
-                } else {
-                    let dec_pos = *p as usize;
-                    let mut v_str = v.to_string();
-                    while v_str.len() <= dec_pos {
-                        v_str.insert(0, '0');
-                    }
-                    v_str.insert(v_str.len() - dec_pos, '.');
-                    v_str.fmt(f)
-                }
-            }
+            DataType::Decimal(v, p) => decimal::format_decimal(*v as i128, *p).fmt(f),
             DataType::Int(n) => n.fmt(f),
             DataType::String(s) => s.fmt(f),
+            DataType::Bool(b) => b.fmt(f),
+            DataType::Date(days) => date::format_date(*days as i64).fmt(f),
+            DataType::Timestamp(seconds) => date::format_timestamp(*seconds).fmt(f),
+            DataType::Null => Ok(()),
         }
     }
 }
@@ -154,34 +268,110 @@ pub struct Selection {
     pub value: Value,
 }
 
+/// previous and undone selection states of a context
+#[derive(Debug, Default)]
+pub struct SelectionHistory {
+    back: Vec<Vec<Selection>>,
+    forward: Vec<Vec<Selection>>,
+}
+
+impl SelectionHistory {
+    /// records the state a context is leaving, which
+    /// discards any states that were stepped back over
+    pub fn push(&mut self, previous: &[Selection]) {
+        self.back.push(previous.to_vec());
+        self.forward.clear();
+    }
+
+    /// steps back from the current state, returning the state to restore
+    pub fn back(&mut self, current: &[Selection]) -> Option<Vec<Selection>> {
+        let previous = self.back.pop()?;
+        self.forward.push(current.to_vec());
+        Some(previous)
+    }
+
+    /// steps forward again after `back`, returning the state to restore
+    pub fn forward(&mut self, current: &[Selection]) -> Option<Vec<Selection>> {
+        let next = self.forward.pop()?;
+        self.back.push(current.to_vec());
+        Some(next)
+    }
+}
+
 pub type DataContextCallback = fn(&DataContext) -> ();
 
 pub struct DataContext<'a> {
     table: &'a Table,
     selection: Vec<Selection>,
+    history: SelectionHistory,
 
     selected_records: Vec<&'a Record>,
     callbacks: Vec<DataContextCallback>,
 }
 
 impl DataContext<'_> {
-    pub fn select(&mut self, selection: &Selection) -> &DataContext {
+    pub fn select(&mut self, selection: &Selection) -> &Self {
+        self.history.push(&self.selection);
         self.selection.push(selection.clone());
         self.update_selected_records();
         self
     }
 
-    pub fn deselect(&mut self, selection: &Selection) -> &DataContext {
-        for (i, select) in self.selection.iter().enumerate() {
-            if select == selection {
-                self.selection.remove(i);
-                break;
-            }
+    pub fn deselect(&mut self, selection: &Selection) -> &Self {
+        if let Some(i) = self.selection.iter().position(|s| s == selection) {
+            self.history.push(&self.selection);
+            self.selection.remove(i);
         }
         self.update_selected_records();
         self
     }
 
+    /// removes all selections
+    pub fn clear(&mut self) -> &Self {
+        self.apply(&[])
+    }
+
+    /// replaces the current selections, e.g. with a bookmark
+    pub fn apply(&mut self, selection: &[Selection]) -> &Self {
+        self.history.push(&self.selection);
+        self.selection = selection.to_vec();
+        self.update_selected_records();
+        self
+    }
+
+    /// restores the previous selection state, returns false
+    /// if there is nothing to go back to
+    pub fn back(&mut self) -> bool {
+        match self.history.back(&self.selection) {
+            Some(previous) => {
+                self.selection = previous;
+                self.update_selected_records();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// redoes a selection state undone by `back`
+    pub fn forward(&mut self) -> bool {
+        match self.history.forward(&self.selection) {
+            Some(next) => {
+                self.selection = next;
+                self.update_selected_records();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_selection(&self) -> &Vec<Selection> {
+        &self.selection
+    }
+
+    pub fn get_table(&self) -> &Table {
+        self.table
+    }
+
     pub fn observe(&mut self, cb: DataContextCallback) {
         self.callbacks.push(cb);
     }
@@ -199,23 +389,63 @@ impl DataContext<'_> {
     }
 
     pub fn count(&self) -> usize {
-        let vals = &self.selected_records;
-        vals.iter().count()
+        self.selected_records.len()
     }
 
-    pub fn sum(&self, col: Column) -> Option<DataType> {
+    pub fn get_selected_records(&self) -> &Vec<&Record> {
+        &self.selected_records
+    }
+
+    /// distinct values of a column among the selected records
+    pub fn get_possible(&self, col: &str) -> Vec<&DataType> {
+        match self.table.get_col_index(col) {
+            None => vec![],
+            Some(i) => self
+                .selected_records
+                .iter()
+                .map(|r| &r[i])
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// the number of selected records per value of a column
+    pub fn get_frequencies(&self, col: &str) -> Vec<(&DataType, usize)> {
+        let rows = self.table.get_possible_ids(&self.selection);
+        self.table.count_values(col, &rows)
+    }
+
+    /// evaluates an expression over the selected records
+    pub fn eval(&self, expr: &Expression) -> Result<DataType, EvalError> {
+        expr.eval(self.table.get_columns(), &self.selected_records)
+    }
+
+    /// evaluates expressions per distinct combination of
+    /// dimension values over the selected records, in the
+    /// order of the sort
+    pub fn group_by(
+        &self,
+        dims: &[Column],
+        exprs: &[Expression],
+        sort: &Sort,
+    ) -> Result<Vec<GroupRow>, EvalError> {
+        ast::group_by(
+            self.table.get_columns(),
+            &self.selected_records,
+            dims,
+            exprs,
+            sort,
+        )
+    }
+
+    pub fn sum(&self, col: Column) -> Option<Result<DataType, ArithmeticError>> {
         self.table.get_col_index(&col).map(|i| {
             self.selected_records
                 .iter()
                 .map(|x| &x[i])
-                .filter(|x| match x {
-                    DataType::Int(_) => true,
-                    DataType::Decimal(_, _) => true,
-                    _ => false,
-                })
-                .cloned()
-                .reduce(DataType::sum)
-                .unwrap_or(DataType::Int(0))
+                .filter(|x| matches!(x, DataType::Int(_) | DataType::Decimal(_, _)))
+                .try_fold(DataType::Int(0), |acc, x| acc.checked_add(x))
         })
     }
 
@@ -224,13 +454,9 @@ impl DataContext<'_> {
             self.selected_records
                 .iter()
                 .map(|x| &x[i])
-                .filter(|x| match x {
-                    DataType::Int(_) => true,
-                    DataType::Decimal(_, _) => true,
-                    _ => false,
-                })
+                .filter(|x| matches!(x, DataType::Int(_) | DataType::Decimal(_, _)))
                 .cloned()
-                .max_by(DataType::cmp)
+                .max()
             // .reduce(DataType::max)
         })
     }
@@ -240,13 +466,9 @@ impl DataContext<'_> {
             self.selected_records
                 .iter()
                 .map(|x| &x[i])
-                .filter(|x| match x {
-                    DataType::Int(_) => true,
-                    DataType::Decimal(_, _) => true,
-                    _ => false,
-                })
+                .filter(|x| matches!(x, DataType::Int(_) | DataType::Decimal(_, _)))
                 .cloned()
-                .min_by(DataType::cmp)
+                .min()
             // .reduce(DataType::min)
         })
     }
@@ -272,16 +494,17 @@ impl Table {
     pub fn new(name: &str, columns: Columns) -> Table {
         Table {
             name: String::from(name),
-            columns: columns,
+            columns,
             index: HashMap::new(),
             records: Vec::new(),
         }
     }
 
-    pub fn new_context(&self) -> DataContext {
+    pub fn new_context(&self) -> DataContext<'_> {
         let mut ctx = DataContext {
             table: self,
             selection: vec![],
+            history: SelectionHistory::default(),
             selected_records: vec![],
             callbacks: vec![],
         };
@@ -289,8 +512,35 @@ impl Table {
         ctx
     }
 
+    /// rebuilds a table from stored records and index entries
+    /// of (column, value, row ids), without re-indexing the records
+    pub(crate) fn from_parts(
+        name: &str,
+        columns: Columns,
+        records: Vec<Record>,
+        index: Vec<(Column, Value, Vec<usize>)>,
+    ) -> Table {
+        Table {
+            name: String::from(name),
+            columns,
+            records,
+            index: index
+                .into_iter()
+                .map(|(column, value, rows)| (IndexValue { column, value }, rows))
+                .collect(),
+        }
+    }
+
+    /// the index entries as (column, value, row ids)
+    pub(crate) fn get_index(&self) -> Vec<(&Column, &Value, &Vec<usize>)> {
+        self.index
+            .iter()
+            .map(|(k, rows)| (&k.column, &k.value, rows))
+            .collect()
+    }
+
     fn index_value(&mut self, v: IndexValue) {
-        let v = self.index.entry(v).or_insert(Vec::new());
+        let v = self.index.entry(v).or_default();
         v.push(self.records.len());
     }
 
@@ -298,17 +548,39 @@ impl Table {
         &self.columns
     }
 
+    /// get the unique values of a column from the index,
+    /// in the order of their typed values
+    pub fn get_values(&self, col: &str) -> Vec<&Value> {
+        let i = match self.get_col_index(col) {
+            Some(i) => i,
+            None => return vec![],
+        };
+        let mut values: Vec<(&DataType, &Value)> = self
+            .index
+            .iter()
+            .filter(|(v, _)| v.column == col)
+            .map(|(v, rows)| (&self.records[rows[0]][i], &v.value))
+            .collect();
+        values.sort();
+        values.into_iter().map(|(_, v)| v).collect()
+    }
+
     pub fn get_col_index(&self, col: &str) -> Option<usize> {
         for (i, column) in self.columns.iter().enumerate() {
             if column == col {
                 return Some(i);
             }
         }
-        return None;
+        None
     }
 
+    /// adds a record, indexing all of its values except nulls,
+    /// which cannot be selected
     pub fn insert(&mut self, record: Record) {
         for (i, field) in record.iter().enumerate() {
+            if *field == DataType::Null {
+                continue;
+            }
             let column_name = self.columns.get(i).expect("column should exist");
             self.index_value(IndexValue {
                 column: String::from(column_name),
@@ -319,16 +591,26 @@ impl Table {
         self.records.push(record);
     }
 
-    pub fn get_rows_by_id(&self, row_ids: &Vec<usize>) -> Vec<&Record> {
+    pub fn get_rows_by_id(&self, row_ids: &[usize]) -> Vec<&Record> {
         let persons = row_ids.iter().filter_map(|id| self.records.get(*id));
-        return persons.collect();
+        persons.collect()
     }
 
-    pub fn get_possible(&self, selection: &Vec<Selection>) -> Vec<&Record> {
-        if selection.len() == 0 {
+    pub fn get_possible(&self, selection: &[Selection]) -> Vec<&Record> {
+        if selection.is_empty() {
             return self.records.iter().collect();
         }
 
+        self.get_rows_by_id(&self.get_possible_ids(selection))
+    }
+
+    /// get the ids of the rows with any of the selected values,
+    /// every row when nothing is selected
+    fn get_possible_ids(&self, selection: &[Selection]) -> Vec<usize> {
+        if selection.is_empty() {
+            return (0..self.records.len()).collect();
+        }
+
         let mut bts = BTreeSet::new();
         let empty = Vec::new();
         for Selection { column, value } in selection {
@@ -350,13 +632,144 @@ impl Table {
             ids.push(id);
         }
 
-        return self.get_rows_by_id(&ids);
+        ids
+    }
+
+    /// get the number of rows per value of a column among the
+    /// given row ids, from the index, in the order of the values
+    ///
+    /// values without any of the rows are left out, nulls are not
+    /// indexed since they cannot be selected, so they are counted
+    /// from the rows, as a group by does
+    pub fn count_values(&self, col: &str, row_ids: &[usize]) -> Vec<(&DataType, usize)> {
+        let i = match self.get_col_index(col) {
+            Some(i) => i,
+            None => return vec![],
+        };
+        let mut possible = vec![false; self.records.len()];
+        for id in row_ids {
+            possible[*id] = true;
+        }
+
+        let mut counts: Vec<(&DataType, usize)> = self
+            .index
+            .iter()
+            .filter(|(v, _)| v.column == col)
+            .map(|(_, rows)| {
+                let count = rows.iter().filter(|row| possible[**row]).count();
+                (&self.records[rows[0]][i], count)
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        let mut nulls = row_ids
+            .iter()
+            .filter(|row| self.records[**row][i] == DataType::Null);
+        if let Some(row) = nulls.next() {
+            counts.push((&self.records[*row][i], nulls.count() + 1));
+        }
+        counts.sort();
+        counts
+    }
+}
+
+/// id of a value in the model's symbol table
+pub type Symbol = u32;
+
+/// the symbol of null cells, which have no value to select
+const NO_SYMBOL: Symbol = Symbol::MAX;
+
+/// model wide table of distinct values, each value gets an id
+/// shared by every table and column it appears in
+///
+/// values are identified by their display form, the same way
+/// selections and associations compare them
+#[derive(Debug, Default)]
+pub struct Symbols {
+    ids: HashMap<Value, Symbol>,
+    values: Vec<DataType>,
+}
+
+impl Symbols {
+    fn intern(&mut self, display: &str, value: &DataType) -> Symbol {
+        if let Some(id) = self.ids.get(display) {
+            return *id;
+        }
+        let id = self.values.len() as Symbol;
+        self.ids.insert(String::from(display), id);
+        self.values.push(value.clone());
+        id
+    }
+
+    /// get the id of a value by its display form
+    pub fn get_id(&self, value: &str) -> Option<Symbol> {
+        self.ids.get(value).copied()
+    }
+
+    pub fn get(&self, id: Symbol) -> &DataType {
+        &self.values[id as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// a set of a field's symbols, one bit per symbol of the field
+#[derive(Clone)]
+struct SymbolSet {
+    bits: Vec<u64>,
+}
+
+impl SymbolSet {
+    fn new(len: usize) -> SymbolSet {
+        SymbolSet {
+            bits: vec![0; len.div_ceil(64)],
+        }
     }
+
+    fn insert(&mut self, id: Symbol) {
+        self.bits[id as usize / 64] |= 1 << (id % 64);
+    }
+
+    fn contains(&self, id: Symbol) -> bool {
+        self.bits
+            .get(id as usize / 64)
+            .is_some_and(|word| word >> (id % 64) & 1 == 1)
+    }
+}
+
+/// the symbols of a table's cells, numbered by the field of their column
+struct TableSymbols {
+    /// the model wide field of each column
+    fields: Vec<usize>,
+    /// symbol per column and row
+    ids: Vec<Vec<Symbol>>,
+    /// per column, the rows of each of its symbols, the table's
+    /// index by symbol
+    rows: Vec<Vec<(Symbol, Vec<usize>)>>,
 }
 
 pub struct Model {
     tables: Vec<Table>,
     columns: HashMap<Column, Vec<usize>>,
+
+    symbols: Symbols,
+    /// model wide column names, a field is a column in every table it appears in
+    fields: HashMap<Column, usize>,
+    /// per field, the id within the field of each of its symbols, so
+    /// sets of a field's values are sized by the field, not the model
+    field_symbols: Vec<HashMap<Symbol, Symbol>>,
+    table_symbols: Vec<TableSymbols>,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Model {
@@ -365,33 +778,128 @@ impl Model {
         Model {
             tables: vec![],
             columns: HashMap::new(),
+            symbols: Symbols::default(),
+            fields: HashMap::new(),
+            field_symbols: vec![],
+            table_symbols: vec![],
         }
     }
 
-    /// adds a table to the model
-    pub fn add_table(&mut self, table: Table) {
+    /// adds a table to the model, interning its values so that
+    /// equal strings across tables share one allocation
+    pub fn add_table(&mut self, mut table: Table) {
         for col in &table.columns {
-            let table_indexes = self.columns.entry(String::from(col)).or_insert(Vec::new());
+            let table_indexes = self.columns.entry(String::from(col)).or_default();
             table_indexes.push(self.tables.len());
         }
+
+        let mut fields = vec![];
+        for c in &table.columns {
+            let next = self.fields.len();
+            fields.push(*self.fields.entry(c.clone()).or_insert(next));
+        }
+        self.field_symbols
+            .resize_with(self.fields.len(), HashMap::new);
+
+        // every non-null cell is in the index, so the symbols are
+        // found once per distinct value instead of once per cell
+        let entries: Vec<(usize, Symbol, Vec<usize>)> = table
+            .get_index()
+            .into_iter()
+            .map(|(column, value, rows)| {
+                let col = table
+                    .get_col_index(column)
+                    .expect("indexed column should exist");
+                let id = self.symbols.intern(value, &table.records[rows[0]][col]);
+                (col, id, rows.clone())
+            })
+            .collect();
+
+        let mut ids = vec![vec![NO_SYMBOL; table.records.len()]; table.columns.len()];
+        let mut symbol_rows = vec![vec![]; table.columns.len()];
+        for (col, id, rows) in entries {
+            let value = self.symbols.get(id);
+            let field_ids = &mut self.field_symbols[fields[col]];
+            let next = field_ids.len() as Symbol;
+            let field_id = *field_ids.entry(id).or_insert(next);
+            for row in &rows {
+                ids[col][*row] = field_id;
+                let cell = &mut table.records[*row][col];
+                if matches!(cell, DataType::String(_)) && cell == value {
+                    *cell = value.clone();
+                }
+            }
+            symbol_rows[col].push((field_id, rows));
+        }
+
+        self.table_symbols.push(TableSymbols {
+            fields,
+            ids,
+            rows: symbol_rows,
+        });
         self.tables.push(table);
     }
 
+    pub fn get_symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// get an empty set for the symbols of a field
+    fn new_symbol_set(&self, field: usize) -> SymbolSet {
+        SymbolSet::new(self.field_symbols[field].len())
+    }
+
+    /// get the id of a value within a field, none when the field
+    /// doesn't have it
+    fn get_field_symbol(&self, field: usize, value: &str) -> Option<Symbol> {
+        let id = self.symbols.get_id(value)?;
+        self.field_symbols[field].get(&id).copied()
+    }
+
+    /// get the rows of a table with a value in any of the sets,
+    /// which are indexed by field, in order
+    ///
+    /// only the distinct values of the columns with a set are
+    /// looked at, and the rows of those that are in it
+    fn get_matching_rows(&self, table: usize, sets: &[Option<SymbolSet>]) -> Vec<usize> {
+        let symbols = &self.table_symbols[table];
+        let mut rows = vec![];
+        for (field, values) in symbols.fields.iter().zip(&symbols.rows) {
+            if let Some(set) = &sets[*field] {
+                for (id, value_rows) in values {
+                    if set.contains(*id) {
+                        rows.extend_from_slice(value_rows);
+                    }
+                }
+            }
+        }
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+
     /// get a table by name from the model
     pub fn get_table(&self, table_name: &str) -> Option<&Table> {
         let table = self.tables.iter().find(|t| t.name == table_name);
         table
     }
 
+    pub fn get_tables(&self) -> &Vec<Table> {
+        &self.tables
+    }
+
+    /// get the names of all columns in the model
+    pub fn get_columns(&self) -> Vec<&Column> {
+        let mut columns: Vec<&Column> = self.columns.keys().collect();
+        columns.sort();
+        columns
+    }
+
     fn get_tables_and_col_indices(&self, col: &str) -> Vec<(&Table, usize)> {
-        let tables_and_col_indices: Vec<(&Table, usize)> = self
-            .tables
+        self.tables
             .iter()
-            .map(|t| (t, t.get_col_index(col)))
-            .filter(|(t, index)| *index != None)
-            .map(|(t, index)| (t, index.unwrap()))
-            .collect();
-        tables_and_col_indices
+            .filter_map(|t| t.get_col_index(col).map(|index| (t, index)))
+            .collect()
     }
 
     /// get the unique values for a given column
@@ -412,13 +920,13 @@ impl Model {
     }
 
     /// starts a new data context for the model
-    pub fn new_data_context(&self) -> ModelContext {
+    pub fn new_data_context(&self) -> ModelContext<'_> {
         ModelContext::new(self)
     }
 
     /// get values and their associative state for a given column
     /// depending on current selections
-    pub fn get_values(&self, col: &str) -> Vec<()> {
+    pub fn get_values(&self, _col: &str) -> Vec<()> {
         vec![]
     }
 
@@ -434,81 +942,304 @@ impl Model {
     // }
 }
 
+pub type ModelContextCallback = fn(&ModelContext) -> ();
+
 pub struct ModelContext<'a> {
     model: &'a Model,
     selection: Vec<Selection>,
+    history: SelectionHistory,
+    callbacks: Vec<ModelContextCallback>,
 }
 
 impl ModelContext<'_> {
-    pub fn new<'a>(model: &'a Model) -> ModelContext {
+    pub fn new(model: &Model) -> ModelContext<'_> {
         ModelContext {
-            model: model,
+            model,
             selection: vec![],
+            history: SelectionHistory::default(),
+            callbacks: vec![],
         }
     }
-    pub fn select(&mut self, select: &Selection) -> &ModelContext {
+    pub fn select(&mut self, select: &Selection) -> &Self {
+        self.history.push(&self.selection);
         self.selection.push(select.clone());
+        self.notify_observers();
         self
     }
 
-    pub fn deselect(&mut self, select: &Selection) -> &ModelContext {
-        self.selection
-            .iter()
-            .position(|p| p == select)
-            .map(|i| self.selection.remove(i));
+    pub fn deselect(&mut self, select: &Selection) -> &Self {
+        if let Some(i) = self.selection.iter().position(|p| p == select) {
+            self.history.push(&self.selection);
+            self.selection.remove(i);
+        }
+        self.notify_observers();
+        self
+    }
+
+    /// removes all selections
+    pub fn clear(&mut self) -> &Self {
+        self.apply(&[])
+    }
 
+    /// replaces the current selections, e.g. with a bookmark
+    pub fn apply(&mut self, selection: &[Selection]) -> &Self {
+        self.history.push(&self.selection);
+        self.selection = selection.to_vec();
+        self.notify_observers();
         self
     }
 
+    /// restores the previous selection state, returns false
+    /// if there is nothing to go back to
+    pub fn back(&mut self) -> bool {
+        match self.history.back(&self.selection) {
+            Some(previous) => {
+                self.selection = previous;
+                self.notify_observers();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// redoes a selection state undone by `back`
+    pub fn forward(&mut self) -> bool {
+        match self.history.forward(&self.selection) {
+            Some(next) => {
+                self.selection = next;
+                self.notify_observers();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_selection(&self) -> &Vec<Selection> {
+        &self.selection
+    }
+
+    pub fn get_model(&self) -> &Model {
+        self.model
+    }
+
+    pub fn observe(&mut self, cb: ModelContextCallback) {
+        self.callbacks.push(cb);
+    }
+
+    fn notify_observers(&self) {
+        for cb in &self.callbacks {
+            cb(self);
+        }
+    }
+
+    /// the numbers of a column among the possible records of the
+    /// first table that has it, none when no table does
+    fn numbers(&self, col: &str) -> Option<Vec<&DataType>> {
+        let table = self.find_table(&[&String::from(col)]).ok()?;
+        let i = table.get_col_index(col)?;
+        Some(
+            self.get_possible_records(table)
+                .into_iter()
+                .map(|r| &r[i])
+                .filter(|x| matches!(x, DataType::Int(_) | DataType::Decimal(_, _)))
+                .collect(),
+        )
+    }
+
+    pub fn sum(&self, col: Column) -> Option<Result<DataType, ArithmeticError>> {
+        self.numbers(&col).map(|numbers| {
+            numbers
+                .into_iter()
+                .try_fold(DataType::Int(0), |acc, x| acc.checked_add(x))
+        })
+    }
+
+    pub fn max(&self, col: Column) -> Option<DataType> {
+        self.numbers(&col)?.into_iter().max().cloned()
+    }
+
+    pub fn min(&self, col: Column) -> Option<DataType> {
+        self.numbers(&col)?.into_iter().min().cloned()
+    }
+
+    /// get the selected values of a column
     pub fn get_selected(&self, col: &str) -> Vec<&DataType> {
-        todo!()
+        let selected: HashSet<&str> = self
+            .selection
+            .iter()
+            .filter(|s| s.column == col)
+            .map(|s| s.value.as_str())
+            .collect();
+
+        self.model
+            .get_all_values(col)
+            .into_iter()
+            .filter(|v| selected.contains(v.to_string().as_str()))
+            .collect()
     }
 
-    pub fn get_possible(&self, col: &str) -> Vec<&DataType> {
-        // country
-        let target_tables = self.model.get_tables_and_col_indices(col);
+    /// get the symbols of every field that are associated with
+    /// the selection, the selected values and every value of the
+    /// records they are in across all tables, none when nothing
+    /// is selected
+    fn get_associated(&self) -> Option<Vec<Option<SymbolSet>>> {
+        if self.selection.is_empty() {
+            return None;
+        }
+        let model = self.model;
 
-        let mut by_col = HashMap::new(); // item=phone
+        let mut selected: Vec<Option<SymbolSet>> = vec![None; model.fields.len()];
         for s in &self.selection {
-            let values = by_col.entry(s.column.as_str()).or_insert(Vec::new());
-            values.push(s.value.as_str());
-        }
-
-        let mut virtual_selection: Vec<Selection> = self.selection.clone();
-
-        // for each column with selected values
-        // find tables
-        // for each table find records matching selection
-        // add all selected values to virtual selection
-        for (selected_column, selected_values) in by_col {
-            let ts = self.model.get_tables_and_col_indices(selected_column);
-            for (table, col_index) in &ts {
-                let possible_records = table.get_possible(&self.selection);
-                for record in &possible_records {
-                    for (index, column) in table.get_columns().iter().enumerate() {
-                        virtual_selection.push(Selection {
-                            column: String::from(column),
-                            value: record[index].to_string(),
-                        });
+            if let Some(field) = model.fields.get(&s.column) {
+                let set = selected[*field].get_or_insert_with(|| model.new_symbol_set(*field));
+                if let Some(id) = model.get_field_symbol(*field, &s.value) {
+                    set.insert(id);
+                }
+            }
+        }
+
+        let mut associated = selected.clone();
+        for (t, symbols) in model.table_symbols.iter().enumerate() {
+            if !symbols.fields.iter().any(|f| selected[*f].is_some()) {
+                continue;
+            }
+            for row in model.get_matching_rows(t, &selected) {
+                for (field, ids) in symbols.fields.iter().zip(&symbols.ids) {
+                    if ids[row] != NO_SYMBOL {
+                        associated[*field]
+                            .get_or_insert_with(|| model.new_symbol_set(*field))
+                            .insert(ids[row]);
                     }
                 }
             }
         }
 
+        Some(associated)
+    }
+
+    /// get the ids of a table's rows that are associated with the current selection
+    fn get_possible_rows(
+        &self,
+        table: usize,
+        associated: &Option<Vec<Option<SymbolSet>>>,
+    ) -> Vec<usize> {
+        match associated {
+            None => (0..self.model.tables[table].records.len()).collect(),
+            Some(sets) => self.model.get_matching_rows(table, sets),
+        }
+    }
+
+    /// get the records of a table that are associated with the current selection
+    pub fn get_possible_records<'t>(&self, table: &'t Table) -> Vec<&'t Record> {
+        match self
+            .model
+            .tables
+            .iter()
+            .position(|t| std::ptr::eq(t, table))
+        {
+            None => vec![],
+            Some(t) => self
+                .get_possible_rows(t, &self.get_associated())
+                .into_iter()
+                .map(|row| &table.records[row])
+                .collect(),
+        }
+    }
+
+    pub fn get_possible(&self, col: &str) -> Vec<&DataType> {
+        let associated = self.get_associated();
+
         // Get values from tables with column
         let mut values: BTreeSet<&DataType> = BTreeSet::new();
-        for (table, col_index) in &target_tables {
-            let records = table.get_possible(&virtual_selection);
-            for record in &records {
-                values.insert(record.get(*col_index).unwrap());
+        for (t, table) in self.model.tables.iter().enumerate() {
+            if let Some(col_index) = table.get_col_index(col) {
+                for row in self.get_possible_rows(t, &associated) {
+                    values.insert(&table.records[row][col_index]);
+                }
             }
         }
 
         values.into_iter().collect()
     }
 
+    /// get the number of possible records per value of a column in
+    /// the first table that has it, the table a `count()` grouped by
+    /// the column is evaluated over, so a key shared by tables is
+    /// counted once per record of that table rather than summed
+    pub fn get_frequencies(&self, col: &str) -> Vec<(&DataType, usize)> {
+        let t = match self
+            .model
+            .tables
+            .iter()
+            .position(|t| t.get_col_index(col).is_some())
+        {
+            Some(t) => t,
+            None => return vec![],
+        };
+        let rows = self.get_possible_rows(t, &self.get_associated());
+        self.model.tables[t].count_values(col, &rows)
+    }
+
+    /// get the values of a column that are not associated with the current selection
     pub fn get_excluded(&self, col: &str) -> Vec<&DataType> {
-        todo!()
+        let possible: HashSet<&DataType> = self.get_possible(col).into_iter().collect();
+
+        self.model
+            .get_all_values(col)
+            .into_iter()
+            .filter(|v| !possible.contains(v))
+            .collect()
+    }
+
+    /// finds the first table that has all the given columns
+    fn find_table(&self, columns: &[&String]) -> Result<&Table, EvalError> {
+        self.model
+            .tables
+            .iter()
+            .find(|t| columns.iter().all(|c| t.get_col_index(c).is_some()))
+            .ok_or_else(|| {
+                let names: Vec<&str> = columns.iter().map(|c| c.as_str()).collect();
+                EvalError {
+                    message: format!("no table has all of the columns {}", names.join(", ")),
+                }
+            })
+    }
+
+    /// evaluates an expression over the possible records of the
+    /// first table that has all the columns it references
+    pub fn eval(&self, expr: &Expression) -> Result<DataType, EvalError> {
+        let table = self.find_table(&expr.get_variables())?;
+
+        expr.eval(table.get_columns(), &self.get_possible_records(table))
+    }
+
+    /// evaluates expressions per distinct combination of
+    /// dimension values over the possible records, in the
+    /// order of the sort
+    pub fn group_by(
+        &self,
+        dims: &[Column],
+        exprs: &[Expression],
+        sort: &Sort,
+    ) -> Result<Vec<GroupRow>, EvalError> {
+        let dim_exprs: Vec<Expression> =
+            dims.iter().map(|d| ast::dimension_expression(d)).collect();
+        let mut columns: Vec<&String> = vec![];
+        for expr in dim_exprs.iter().chain(exprs) {
+            columns.extend(expr.get_variables());
+        }
+        if let SortKey::Expression(expr) = &sort.key {
+            columns.extend(expr.get_variables());
+        }
+        let table = self.find_table(&columns)?;
+
+        ast::group_by(
+            table.get_columns(),
+            &self.get_possible_records(table),
+            dims,
+            exprs,
+            sort,
+        )
     }
 }
 
@@ -526,7 +1257,7 @@ impl BetterSelection<'_> {
             bs.selected_values.insert(k, v);
         }
 
-        return bs;
+        bs
     }
 }
 
@@ -535,20 +1266,147 @@ mod test {
     use super::*;
 
     #[test]
+    #[allow(unused_variables, unused_assignments)]
     fn make_selection() {
         let mut bs = BetterSelection{ selected_values: HashMap::new() };
 
         bs = BetterSelection::new(vec![("name", vec!["ni", "ai"]), ("country", vec!["swe"])]);
     }
 
+    #[test]
+    fn checked_decimal_arithmetic() {
+        let a = DataType::Decimal(150, 2);
+        let b = DataType::Int(-3);
+        assert_eq!(a.checked_add(&b), Ok(DataType::Decimal(-150, 2)));
+        assert_eq!(a.checked_sub(&b), Ok(DataType::Decimal(450, 2)));
+        assert_eq!(a.checked_mul(&b), Ok(DataType::Decimal(-450, 2)));
+        assert_eq!(
+            a.checked_div(&b, 3, Rounding::HalfUp),
+            Ok(DataType::Decimal(-500, 3))
+        );
+        assert_eq!(
+            DataType::Int(2).checked_div(&DataType::Int(3), 2, Rounding::HalfEven),
+            Ok(DataType::Decimal(67, 2))
+        );
+        assert!(a
+            .checked_div(&DataType::Decimal(0, 1), 2, Rounding::Down)
+            .is_err());
+        assert_eq!(
+            DataType::Decimal(-1255, 3).round(2, Rounding::HalfEven),
+            Ok(DataType::Decimal(-126, 2))
+        );
+        assert_eq!(
+            DataType::Decimal(15, 1).round(0, Rounding::Floor),
+            Ok(DataType::Int(1))
+        );
+
+        // the scaled value overflows i64 even though both inputs fit
+        let big = DataType::Int(i64::MAX / 2);
+        assert!(big.checked_add(&DataType::Decimal(1, 1)).is_err());
+        assert!(DataType::Int(i64::MAX)
+            .checked_add(&DataType::Int(1))
+            .is_err());
+        assert!(DataType::Decimal(1, 10) < DataType::Int(i64::MAX));
+
+        let t = table(
+            "amounts",
+            vec![vec!["amount"], vec!["9223372036854775807"], vec!["1"]],
+        );
+        let ctx = t.new_context();
+        assert!(ctx.sum(String::from("amount")).unwrap().is_err());
+        match ctx.eval(&Expression::from_string("sum(amount)").unwrap()) {
+            Ok(v) => panic!("expected an overflow, got {}", v),
+            Err(e) => assert_eq!(e.message, "sum(amount): decimal overflow in add"),
+        }
+    }
+
+    #[test]
+    fn parse_and_display_numbers() {
+        assert_eq!(DataType::from_string("1e5"), DataType::Int(100000));
+        assert_eq!(DataType::from_string("-.5"), DataType::Decimal(-5, 1));
+        assert_eq!(DataType::from_string("-.5").to_string(), "-0.5");
+        assert_eq!(DataType::from_string("-1.05").to_string(), "-1.05");
+        assert_eq!(DataType::from_string("inf"), DataType::String("inf".into()));
+        assert_eq!(
+            DataType::from_string("92233720368547758070"),
+            DataType::String("92233720368547758070".into())
+        );
+        assert_eq!(
+            DataType::parse("1.234,5", &NumberFormat::decimal_comma()),
+            DataType::Decimal(12345, 1)
+        );
+    }
+
+    #[test]
+    fn values_have_one_order() {
+        let mut values = data_types(&vec!["b", "10", "", "9.5", "A", "9.50", "-1", "9", "a"]);
+        values[2] = DataType::Null;
+        values.push(DataType::Bool(true));
+        values.push(DataType::Decimal(90, 1));
+        values.push(DataType::from_string("2023-01-02"));
+        values.push(DataType::from_string("2023-01-01 12:00"));
+        values.push(DataType::from_string("2023-01-02 00:00"));
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                DataType::Null,
+                DataType::Bool(true),
+                DataType::Int(-1),
+                DataType::Int(9),
+                DataType::Decimal(90, 1),
+                DataType::Decimal(95, 1),
+                DataType::Decimal(950, 2),
+                DataType::Int(10),
+                DataType::Timestamp(19_358 * 86_400 + 12 * 3600),
+                DataType::Date(19_359),
+                DataType::Timestamp(19_359 * 86_400),
+                DataType::from_string("A"),
+                DataType::from_string("a"),
+                DataType::from_string("b"),
+            ]
+        );
+        for a in &values {
+            for b in &values {
+                assert_eq!(a.cmp(b) == Ordering::Equal, a == b);
+            }
+        }
+
+        let a = DataType::from_string("a");
+        let b = DataType::from_string("B");
+        assert_eq!(a.cmp(&b), Ordering::Greater);
+        assert_eq!(a.collate(&b, Collation::CaseInsensitive), Ordering::Less);
+
+        let mut m = Model::new();
+        m.add_table(table(
+            "t1",
+            vec![vec!["n"], vec!["10"], vec!["9.5"], vec!["9"]],
+        ));
+        assert_eq!(
+            m.get_all_values("n"),
+            vec![
+                &DataType::Int(9),
+                &DataType::Decimal(95, 1),
+                &DataType::Int(10)
+            ]
+        );
+        assert_eq!(
+            m.get_table("t1").unwrap().get_values("n"),
+            vec!["9", "9.5", "10"]
+        );
+    }
+
+    #[allow(clippy::into_iter_on_ref)]
     fn copy_strings(strs: &Vec<&str>) -> Vec<String> {
         strs.into_iter().map(|&s| String::from(s)).collect()
     }
 
+    #[allow(clippy::into_iter_on_ref)]
     fn data_types(strs: &Vec<&str>) -> Vec<DataType> {
         strs.into_iter().map(|s| DataType::from_string(s)).collect()
     }
 
+    #[allow(clippy::get_first)]
     fn table(name: &str, data: Vec<Vec<&str>>) -> Table {
         let table_header = data.get(0).expect("table header must exist");
         let mut t = Table::new(name, copy_strings(table_header));
@@ -558,6 +1416,66 @@ mod test {
         t
     }
 
+    #[test]
+    fn symbols_are_shared_across_tables() {
+        let m = fixture_model();
+        let symbols = m.get_symbols();
+
+        let ni = symbols.get_id("ni").unwrap();
+        assert_eq!(symbols.get(ni), &DataType::from_string("ni"));
+        let name = m.fields["name"];
+        assert_eq!(m.table_symbols[0].fields[0], name);
+        assert_eq!(m.table_symbols[1].fields[0], name);
+        let ni = m.get_field_symbol(name, "ni").unwrap();
+        assert_eq!(m.table_symbols[0].ids[0][0], ni);
+        assert_eq!(m.table_symbols[1].ids[0][0], ni);
+        assert_eq!(m.get_field_symbol(m.fields["item"], "ni"), None);
+
+        match (&m.tables[0].records[0][0], &m.tables[1].records[1][0]) {
+            (DataType::String(a), DataType::String(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => panic!("expected strings"),
+        }
+        assert_eq!(symbols.get_id("nobody"), None);
+    }
+
+    #[test]
+    fn matching_rows_by_symbol() {
+        let m = fixture_model();
+        let set = |field: usize, values: &[&str]| {
+            let mut set = m.new_symbol_set(field);
+            for v in values {
+                set.insert(m.get_field_symbol(field, v).unwrap());
+            }
+            Some(set)
+        };
+        let mut sets = vec![None; m.fields.len()];
+        sets[m.fields["item"]] = set(m.fields["item"], &["toy", "keys"]);
+        assert_eq!(m.get_matching_rows(1, &sets), vec![1, 2]);
+
+        // rows matching several columns are listed once
+        sets[m.fields["name"]] = set(m.fields["name"], &["ni", "qe"]);
+        assert_eq!(m.get_matching_rows(1, &sets), vec![0, 1, 2, 3]);
+        assert_eq!(m.get_matching_rows(0, &sets), vec![0, 2, 3]);
+        let none = vec![None; m.fields.len()];
+        assert!(m.get_matching_rows(2, &none).is_empty());
+    }
+
+    #[test]
+    fn model_possible_records() {
+        let m = fixture_model();
+        let mut ctx = m.new_data_context();
+        let t1 = m.get_table("t1").unwrap();
+        assert_eq!(ctx.get_possible_records(t1).len(), 5);
+
+        ctx.select(&sel("item", "toy"));
+        assert_eq!(ctx.get_possible("name"), vec![&DataType::from_string("ai")]);
+        assert_eq!(ctx.get_possible_records(t1), vec![&t1.records[1]]);
+
+        // values that are in no table select nothing
+        ctx.apply(&[sel("item", "car")]);
+        assert!(ctx.get_possible_records(t1).is_empty());
+    }
+
     fn model(tables: Vec<Table>) -> Model {
         let mut m = Model::new();
         for t in tables {
@@ -566,6 +1484,7 @@ mod test {
         m
     }
 
+    #[allow(clippy::let_and_return)]
     fn fixture_model() -> Model {
         let data1 = vec![
             vec!["name", "country"],
@@ -667,4 +1586,119 @@ mod test {
         
         assert_eq!(possible_prices, vec![&DataType::from_string("1.5"), &DataType::from_string("10")])
     }
+
+    #[test]
+    fn value_frequencies() {
+        let model = fixture_model();
+        let t1 = model.get_table("t1").unwrap();
+        let freq = |v: Vec<(&DataType, usize)>| -> Vec<(String, usize)> {
+            v.into_iter().map(|(v, n)| (v.to_string(), n)).collect()
+        };
+
+        let mut ctx = t1.new_context();
+        assert_eq!(
+            freq(ctx.get_frequencies("country")),
+            vec![("cn".into(), 1), ("swe".into(), 3), ("usa".into(), 1)]
+        );
+        ctx.select(&sel("name", "ni"));
+        assert_eq!(freq(ctx.get_frequencies("country")), vec![("swe".into(), 2)]);
+        assert!(ctx.get_frequencies("height").is_empty());
+
+        let mut ctx = model.new_data_context();
+        ctx.select(&sel("item", "phone"));
+        ctx.select(&sel("item", "sandwich"));
+        assert_eq!(
+            freq(ctx.get_frequencies("country")),
+            vec![("cn".into(), 1), ("swe".into(), 2)]
+        );
+        // names are counted in t1, the first table they are in
+        assert_eq!(
+            freq(ctx.get_frequencies("name")),
+            vec![("ni".into(), 2), ("qe".into(), 1)]
+        );
+        let group_by = ctx
+            .group_by(
+                &[String::from("name")],
+                &[Expression::Count],
+                &Sort::default(),
+            )
+            .unwrap();
+        let counts: Vec<(String, usize)> = group_by
+            .iter()
+            .map(|(key, values)| (key[0].to_string(), values[0].to_string().parse().unwrap()))
+            .collect();
+        assert_eq!(freq(ctx.get_frequencies("name")), counts);
+
+        // nulls are counted as they are grouped
+        let mut t = Table::new("t", vec![String::from("name")]);
+        for value in [DataType::from_string("ni"), DataType::Null, DataType::Null] {
+            t.insert(vec![value]);
+        }
+        let ctx = t.new_context();
+        assert_eq!(
+            freq(ctx.get_frequencies("name")),
+            vec![("".into(), 2), ("ni".into(), 1)]
+        );
+    }
+
+    fn sel(column: &str, value: &str) -> Selection {
+        Selection {
+            column: String::from(column),
+            value: String::from(value),
+        }
+    }
+
+    #[test]
+    fn data_context_back_and_forward() {
+        let t = table(
+            "t1",
+            vec![vec!["name", "country"], vec!["ni", "swe"], vec!["qe", "cn"], vec!["ai", "swe"]],
+        );
+        let mut ctx = t.new_context();
+
+        ctx.select(&sel("country", "swe"));
+        ctx.select(&sel("name", "qe"));
+        assert_eq!(ctx.count(), 3);
+
+        assert!(ctx.back());
+        assert_eq!(ctx.get_selection(), &vec![sel("country", "swe")]);
+        assert_eq!(ctx.count(), 2);
+
+        assert!(ctx.back());
+        assert_eq!(ctx.count(), 3);
+        assert!(!ctx.back());
+
+        assert!(ctx.forward());
+        assert_eq!(ctx.count(), 2);
+
+        // a new selection drops the states stepped back over
+        ctx.select(&sel("name", "ai"));
+        assert!(!ctx.forward());
+
+        ctx.clear();
+        assert!(ctx.get_selection().is_empty());
+        assert!(ctx.back());
+        assert_eq!(ctx.get_selection(), &vec![sel("country", "swe"), sel("name", "ai")]);
+    }
+
+    #[test]
+    fn model_context_back_and_forward() {
+        let model = fixture_model();
+
+        let mut ctx = model.new_data_context();
+        ctx.select(&sel("item", "phone"));
+        ctx.apply(&[sel("item", "sandwich")]);
+        assert_eq!(ctx.get_possible("name"), vec![&DataType::from_string("qe")]);
+
+        assert!(ctx.back());
+        assert_eq!(ctx.get_possible("name"), vec![&DataType::from_string("ni")]);
+
+        assert!(ctx.forward());
+        assert_eq!(ctx.get_possible("name"), vec![&DataType::from_string("qe")]);
+
+        // deselecting something not selected is not a new state
+        ctx.deselect(&sel("item", "phone"));
+        assert!(ctx.back());
+        assert_eq!(ctx.get_selection(), &vec![sel("item", "phone")]);
+    }
 }
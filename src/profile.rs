@@ -0,0 +1,227 @@
+//! per column summaries of a table, to check what a file
+//! was loaded as before working with it
+
+use std::collections::BTreeMap;
+
+use crate::ast::top_n;
+use crate::model::{Column, DataType, Table};
+
+/// how many unparseable entries are kept as samples
+const SAMPLES: usize = 3;
+
+/// the summary of one column of a table
+#[derive(Debug, PartialEq)]
+pub struct ColumnProfile {
+    pub column: Column,
+    pub ints: usize,
+    pub decimals: usize,
+    pub strings: usize,
+    pub bools: usize,
+    /// dates and timestamps
+    pub dates: usize,
+    /// nulls and empty strings
+    pub nulls: usize,
+    /// distinct values, not counting nulls and empty strings
+    pub distinct: usize,
+    /// the smallest and largest numbers or dates in a column
+    /// of them, the smallest and largest values otherwise
+    pub min: Option<DataType>,
+    pub max: Option<DataType>,
+    /// the most frequent values with their counts
+    pub top: Vec<(DataType, usize)>,
+    /// strings in a column where most values are numbers or
+    /// dates, likely entries that did not parse
+    pub unparseable: Vec<DataType>,
+}
+
+impl ColumnProfile {
+    /// whether most values are numbers or dates
+    fn is_parsed(&self) -> bool {
+        self.ints + self.decimals + self.dates > self.strings + self.bools
+    }
+}
+
+/// the columns of the report made by `to_rows`
+pub fn report_columns() -> Vec<String> {
+    [
+        "column",
+        "int",
+        "decimal",
+        "string",
+        "bool",
+        "date",
+        "null",
+        "distinct",
+        "min",
+        "max",
+        "top",
+        "unparseable",
+    ]
+    .iter()
+    .map(|c| String::from(*c))
+    .collect()
+}
+
+/// one report row per column profile
+pub fn to_rows(profiles: &[ColumnProfile]) -> Vec<Vec<DataType>> {
+    let count = |n: usize| DataType::Int(n as i64);
+    let or_null = |v: &Option<DataType>| v.clone().unwrap_or(DataType::Null);
+    profiles
+        .iter()
+        .map(|p| {
+            let top: Vec<String> = p
+                .top
+                .iter()
+                .map(|(v, n)| format!("{} ({})", v, n))
+                .collect();
+            let samples: Vec<String> = p.unparseable.iter().map(|v| v.to_string()).collect();
+            vec![
+                DataType::String(p.column.as_str().into()),
+                count(p.ints),
+                count(p.decimals),
+                count(p.strings),
+                count(p.bools),
+                count(p.dates),
+                count(p.nulls),
+                count(p.distinct),
+                or_null(&p.min),
+                or_null(&p.max),
+                DataType::String(top.join(", ").into()),
+                DataType::String(samples.join(", ").into()),
+            ]
+        })
+        .collect()
+}
+
+/// profiles every column of a table, keeping the `top` most
+/// frequent values of each
+pub fn profile(table: &Table, top: usize) -> Vec<ColumnProfile> {
+    table
+        .get_columns()
+        .iter()
+        .enumerate()
+        .map(|(i, column)| profile_column(table, column, i, top))
+        .collect()
+}
+
+fn profile_column(table: &Table, column: &Column, i: usize, top: usize) -> ColumnProfile {
+    let mut profile = ColumnProfile {
+        column: column.clone(),
+        ints: 0,
+        decimals: 0,
+        strings: 0,
+        bools: 0,
+        dates: 0,
+        nulls: 0,
+        distinct: 0,
+        min: None,
+        max: None,
+        top: vec![],
+        unparseable: vec![],
+    };
+
+    let mut counts: BTreeMap<&DataType, usize> = BTreeMap::new();
+    for record in &table.records {
+        let value = &record[i];
+        match value {
+            DataType::Null => profile.nulls += 1,
+            DataType::String(s) if s.is_empty() => profile.nulls += 1,
+            DataType::String(_) => profile.strings += 1,
+            DataType::Int(_) => profile.ints += 1,
+            DataType::Decimal(_, _) => profile.decimals += 1,
+            DataType::Bool(_) => profile.bools += 1,
+            DataType::Date(_) | DataType::Timestamp(_) => profile.dates += 1,
+        }
+        if !value.is_null() {
+            *counts.entry(value).or_default() += 1;
+        }
+    }
+    profile.distinct = counts.len();
+
+    let parsed = profile.is_parsed();
+    let is_parsed = |v: &DataType| !matches!(v, DataType::String(_) | DataType::Bool(_));
+    let ranged = || counts.keys().filter(|v| !parsed || is_parsed(v));
+    profile.min = ranged().next().map(|v| (*v).clone());
+    profile.max = ranged().next_back().map(|v| (*v).clone());
+
+    if parsed {
+        // samples in the order they appear in the table
+        for record in &table.records {
+            if profile.unparseable.len() == SAMPLES {
+                break;
+            }
+            let value = &record[i];
+            if matches!(value, DataType::String(_))
+                && !value.is_null()
+                && !profile.unparseable.contains(value)
+            {
+                profile.unparseable.push(value.clone());
+            }
+        }
+    }
+
+    // most frequent first, equally frequent in value order
+    profile.top = top_n(
+        counts,
+        |a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)),
+        0,
+        Some(top),
+    )
+    .into_iter()
+    .map(|(v, n)| (v.clone(), n))
+    .collect();
+    profile
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profile_columns() {
+        let mut table = Table::new("t", vec![String::from("country"), String::from("age")]);
+        for (country, age) in [
+            ("swe", "35"),
+            ("cn", "1.5"),
+            ("swe", "seventyone"),
+            ("", "35"),
+            ("us", "seventyone"),
+        ] {
+            table.insert(vec![
+                DataType::from_string(country),
+                DataType::from_string(age),
+            ]);
+        }
+        table.insert(vec![DataType::from_string("cn"), DataType::Null]);
+
+        let profiles = profile(&table, 2);
+        let country = &profiles[0];
+        assert_eq!(
+            (country.strings, country.nulls, country.distinct),
+            (5, 1, 3)
+        );
+        assert_eq!(country.min, Some(DataType::from_string("cn")));
+        assert_eq!(country.max, Some(DataType::from_string("us")));
+        assert_eq!(
+            country.top,
+            vec![
+                (DataType::from_string("cn"), 2),
+                (DataType::from_string("swe"), 2)
+            ]
+        );
+        assert!(country.unparseable.is_empty());
+
+        let age = &profiles[1];
+        assert_eq!(
+            (age.ints, age.decimals, age.strings, age.nulls),
+            (2, 1, 2, 1)
+        );
+        assert_eq!(age.min, Some(DataType::Decimal(15, 1)));
+        assert_eq!(age.max, Some(DataType::Int(35)));
+        assert_eq!(age.unparseable, vec![DataType::from_string("seventyone")]);
+
+        let rows = to_rows(&profiles);
+        assert_eq!(rows[1].len(), report_columns().len());
+        assert_eq!(rows[1][10], DataType::from_string("35 (2), seventyone (2)"));
+    }
+}
@@ -0,0 +1,764 @@
+use crate::ast::{dimension_expression, Expression, Sort};
+use crate::bookmark::Bookmarks;
+use crate::editor::Completer;
+use crate::export;
+use crate::format::{Format, FORMATS};
+use crate::model;
+use crate::profile;
+
+const COMMANDS: [&str; 12] = [
+    "select", "deselect", "back", "forward", "clear", "bookmark", "eval", "show", "records", "set",
+    "export", "profile",
+];
+/// how many of the most frequent values a profile shows per column
+const PROFILE_TOP: usize = 3;
+const BOOKMARK_COMMANDS: [&str; 4] = ["save", "apply", "delete", "list"];
+
+#[derive(Debug)]
+pub struct CommandError {
+    pub message: String,
+}
+
+impl CommandError {
+    fn new(message: &str) -> CommandError {
+        CommandError {
+            message: String::from(message),
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// parses `column=value1,value2` into one selection per value
+fn parse_selections(arg: &str) -> Result<Vec<model::Selection>, CommandError> {
+    if !arg.contains('=') {
+        return Err(CommandError::new("Invalid select argument"));
+    }
+
+    let parts: Vec<&str> = arg.splitn(2, '=').collect();
+
+    Ok(parts[1]
+        .split(',')
+        .map(|val| model::Selection {
+            column: String::from(parts[0]),
+            value: String::from(val),
+        })
+        .collect())
+}
+
+/// parses `[by <key> [asc|desc]] [limit <n>] [offset <n>]`,
+/// the key is `value`, `count` or an aggregation
+fn parse_sort(args: &[&str]) -> Result<Sort, CommandError> {
+    let usage = || {
+        CommandError::new("Usage: show <dimension> [by <key> [asc|desc]] [limit <n>] [offset <n>]")
+    };
+    let mut sort = Sort::default();
+    let mut rest = args;
+    while let [option, tail @ ..] = rest {
+        let end = tail
+            .iter()
+            .position(|t| *t == "limit" || *t == "offset" || *t == "by")
+            .unwrap_or(tail.len());
+        let (value, tail) = tail.split_at(end);
+        if value.is_empty() {
+            return Err(usage());
+        }
+        let text = value.join(" ");
+        match *option {
+            "by" => {
+                let parsed = Sort::from_string(&text).map_err(|e| CommandError {
+                    message: format!("{}: {}", text, e.message),
+                })?;
+                sort.key = parsed.key;
+                sort.descending = parsed.descending;
+            }
+            "limit" => sort.limit = Some(text.parse().map_err(|_| usage())?),
+            "offset" => sort.offset = text.parse().map_err(|_| usage())?,
+            _ => return Err(usage()),
+        }
+        rest = tail;
+    }
+    Ok(sort)
+}
+
+/// splits the text after `show` into the dimension and the words of
+/// the options after it, which start at a `by`, `limit` or `offset`
+/// outside of parentheses and quotes
+fn split_dimension(text: &str) -> (&str, Vec<&str>) {
+    let text = text.trim();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut word_start = false;
+    for (i, c) in text.char_indices() {
+        let at_word = word_start && !c.is_whitespace();
+        word_start = c.is_whitespace();
+        match c {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            _ if at_word && depth == 0 && !quoted => {
+                let options: Vec<&str> = text[i..].split_whitespace().collect();
+                if matches!(options[0], "by" | "limit" | "offset") {
+                    return (text[..i].trim_end(), options);
+                }
+            }
+            _ => (),
+        }
+    }
+    (text, vec![])
+}
+
+/// executes interactive commands against the context of a model,
+/// selections in one table carry over to the tables associated with it
+pub struct Repl<'a> {
+    ctx: model::ModelContext<'a>,
+    bookmarks: Bookmarks,
+    format: Format,
+    collation: model::Collation,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(ctx: model::ModelContext<'a>, bookmarks: Bookmarks) -> Repl<'a> {
+        Repl {
+            ctx,
+            bookmarks,
+            format: Format::Table,
+            collation: model::Collation::Binary,
+        }
+    }
+
+    pub fn set_format(&mut self, format: Format) {
+        self.format = format;
+    }
+
+    fn print(&self, columns: &[String], rows: &[Vec<model::DataType>]) {
+        print!("{}", self.format.format(columns, rows));
+    }
+
+    /// executes a single command line
+    pub fn execute(&mut self, line: &str) -> Result<(), CommandError> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        match tokens.as_slice() {
+            [cmd @ ("select" | "deselect"), arg] => {
+                let sel = parse_selections(arg)?;
+                self.print(
+                    &[String::from(*cmd), String::from("value")],
+                    &sel.iter()
+                        .map(|s| {
+                            vec![
+                                model::DataType::String(s.column.as_str().into()),
+                                model::DataType::String(s.value.as_str().into()),
+                            ]
+                        })
+                        .collect::<Vec<_>>(),
+                );
+
+                let mut state = self.ctx.get_selection().clone();
+                if *cmd == "select" {
+                    for s in sel {
+                        if !state.contains(&s) {
+                            state.push(s);
+                        }
+                    }
+                } else {
+                    state.retain(|s| !sel.contains(s));
+                }
+                // an unchanged selection isn't a step to go back to
+                if state != *self.ctx.get_selection() {
+                    self.ctx.apply(&state);
+                }
+                Ok(())
+            }
+            ["back"] => {
+                if !self.ctx.back() {
+                    return Err(CommandError::new("Nothing to go back to"));
+                }
+                Ok(())
+            }
+            ["forward"] => {
+                if !self.ctx.forward() {
+                    return Err(CommandError::new("Nothing to go forward to"));
+                }
+                Ok(())
+            }
+            ["clear"] => {
+                self.ctx.clear();
+                Ok(())
+            }
+            ["bookmark", rest @ ..] => self.bookmark(rest),
+            ["eval", _, ..] => {
+                let text = line.trim_start()["eval".len()..].trim();
+                let expr = Expression::from_string(text).map_err(|e| CommandError {
+                    message: format!("{}: {}", text, e.message),
+                })?;
+                let value = self
+                    .ctx
+                    .eval(&expr)
+                    .map_err(|e| CommandError { message: e.message })?;
+                self.print(&[expr.to_string()], &[vec![value]]);
+                Ok(())
+            }
+            ["show", _, ..] => {
+                let (columns, rows) = self.show(&line.trim_start()["show".len()..])?;
+                self.print(&columns, &rows);
+                Ok(())
+            }
+            ["records"] => {
+                let t = self.ctx.get_model().get_tables().first();
+                let t = t.ok_or_else(|| CommandError::new("No tables loaded"))?;
+                self.records(t);
+                Ok(())
+            }
+            ["records", table] => {
+                self.records(self.table(table)?);
+                Ok(())
+            }
+            ["export", table, path, rest @ ..] => self.export(table, path, rest),
+            ["profile", table] => {
+                let t = self.table(table)?;
+                let profiles = profile::profile(t, PROFILE_TOP);
+                self.print(&profile::report_columns(), &profile::to_rows(&profiles));
+                Ok(())
+            }
+            ["set", "format", name] => {
+                self.format = Format::from_string(name).ok_or_else(|| CommandError {
+                    message: format!("Unknown format {}, expected one of {}", name, FORMATS.join(", ")),
+                })?;
+                Ok(())
+            }
+            ["set", "collation", name] => {
+                self.collation =
+                    model::Collation::from_string(name).ok_or_else(|| CommandError {
+                        message: format!(
+                            "Unknown collation {}, expected one of {}",
+                            name,
+                            model::COLLATIONS.join(", ")
+                        ),
+                    })?;
+                Ok(())
+            }
+            [] => Err(CommandError::new("Provide a valid command")),
+            _ => Err(CommandError::new("Unrecognized command")),
+        }
+    }
+
+    /// executes commands separated by newlines or `;`, skipping
+    /// blank lines and `#` comments, and stops at the first error
+    pub fn run_batch(&mut self, script: &str) -> Result<(), CommandError> {
+        for (n, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            for command in line.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+                self.execute(command).map_err(|e| CommandError {
+                    message: format!("line {}: {}: {}", n + 1, command, e.message),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// a table of the model by name
+    fn table(&self, name: &str) -> Result<&model::Table, CommandError> {
+        self.ctx
+            .get_model()
+            .get_table(name)
+            .ok_or_else(|| CommandError {
+                message: format!("No table named {}", name),
+            })
+    }
+
+    /// prints the records of a table associated with the selection
+    fn records(&self, table: &model::Table) {
+        let rows: Vec<Vec<model::DataType>> = self
+            .ctx
+            .get_possible_records(table)
+            .iter()
+            .map(|r| r.to_vec())
+            .collect();
+        self.print(table.get_columns(), &rows);
+    }
+
+    /// the rows of `show <dimension> [options]`, the dimension is a
+    /// column or an expression, which may hold spaces
+    fn show(&self, text: &str) -> Result<(Vec<String>, Vec<Vec<model::DataType>>), CommandError> {
+        let (dimension, args) = split_dimension(text);
+        let columns = self.ctx.get_model().get_columns();
+        let is_column = columns.iter().any(|c| c.as_str() == dimension);
+        if !is_column && matches!(dimension_expression(dimension), Expression::Variable(_)) {
+            return Err(CommandError {
+                message: format!("No column named {}", dimension),
+            });
+        }
+        let rows: Vec<Vec<model::DataType>> = if is_column && args.is_empty() {
+            let mut counts = self.ctx.get_frequencies(dimension);
+            counts.sort_by(|(a, _), (b, _)| a.collate(b, self.collation));
+            counts
+                .into_iter()
+                .map(|(v, n)| vec![v.clone(), model::DataType::Int(n as i64)])
+                .collect()
+        } else {
+            let sort = Sort {
+                collation: self.collation,
+                ..parse_sort(&args)?
+            };
+            self.ctx
+                .group_by(&[String::from(dimension)], &[Expression::Count], &sort)
+                .map_err(|e| CommandError { message: e.message })?
+                .into_iter()
+                .map(|(key, values)| key.into_iter().chain(values).collect())
+                .collect()
+        };
+        Ok((vec![String::from(dimension), String::from("count")], rows))
+    }
+
+    fn export(&self, table: &str, path: &str, args: &[&str]) -> Result<(), CommandError> {
+        let columns: Option<Vec<String>> = match args {
+            [] => None,
+            ["--columns", columns @ ..] if !columns.is_empty() => Some(
+                columns
+                    .iter()
+                    .flat_map(|c| c.split(','))
+                    .filter(|c| !c.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            _ => {
+                return Err(CommandError::new(
+                    "Usage: export <table> <path> [--columns <column>,...]",
+                ))
+            }
+        };
+
+        let t = self.table(table)?;
+        let count = export::export(
+            std::path::Path::new(path),
+            t.get_columns(),
+            &self.ctx.get_possible_records(t),
+            columns.as_deref(),
+        )
+        .map_err(|e| CommandError { message: e.message })?;
+        eprintln!("Exported {} records to {}", count, path);
+        Ok(())
+    }
+
+    fn bookmark(&mut self, args: &[&str]) -> Result<(), CommandError> {
+        let save = |bookmarks: &Bookmarks| {
+            bookmarks
+                .save()
+                .map_err(|e| CommandError { message: e.message })
+        };
+
+        match args {
+            ["save", name] => {
+                self.bookmarks.add(name, self.ctx.get_selection());
+                save(&self.bookmarks)
+            }
+            ["delete", name] => {
+                if !self.bookmarks.remove(name) {
+                    return Err(CommandError::new("No such bookmark"));
+                }
+                save(&self.bookmarks)
+            }
+            ["apply", name] => {
+                let state = self
+                    .bookmarks
+                    .get(name)
+                    .ok_or_else(|| CommandError::new("No such bookmark"))?
+                    .clone();
+                self.ctx.apply(&state);
+                Ok(())
+            }
+            ["list"] => {
+                for name in self.bookmarks.names() {
+                    let state = self.bookmarks.get(name).unwrap();
+                    let selections: Vec<String> = state
+                        .iter()
+                        .map(|s| format!("{}={}", s.column, s.value))
+                        .collect();
+                    println!("{}: {}", name, selections.join(" "));
+                }
+                Ok(())
+            }
+            _ => Err(CommandError::new(
+                "Usage: bookmark save|apply|delete <name>, bookmark list",
+            )),
+        }
+    }
+}
+
+fn candidates<'a>(words: impl Iterator<Item = &'a str>, prefix: &str, suffix: &str) -> Vec<String> {
+    words
+        .filter(|w| w.starts_with(prefix))
+        .map(|w| format!("{}{}", w, suffix))
+        .collect()
+}
+
+impl Completer for Repl<'_> {
+    fn complete(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let line = &line[..pos];
+        let start = line.rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let word = &line[start..];
+        let previous: Vec<&str> = line[..start].split_whitespace().collect();
+
+        match previous.as_slice() {
+            [] => (start, candidates(COMMANDS.into_iter(), word, " ")),
+            ["select" | "deselect"] => match word.split_once('=') {
+                None => {
+                    let columns = self.ctx.get_model().get_columns();
+                    (
+                        start,
+                        candidates(columns.into_iter().map(String::as_str), word, "="),
+                    )
+                }
+                Some((column, values)) => {
+                    // complete the value after the last comma
+                    let value_start = values.rfind(',').map(|i| i + 1).unwrap_or(0);
+                    let offset = start + column.len() + 1 + value_start;
+                    let column_values: Vec<String> = self
+                        .ctx
+                        .get_model()
+                        .get_all_values(column)
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect();
+                    (
+                        offset,
+                        candidates(
+                            column_values.iter().map(String::as_str),
+                            &values[value_start..],
+                            "",
+                        ),
+                    )
+                }
+            },
+            ["bookmark"] => (start, candidates(BOOKMARK_COMMANDS.into_iter(), word, " ")),
+            ["show"] => {
+                let columns = self.ctx.get_model().get_columns();
+                (
+                    start,
+                    candidates(columns.into_iter().map(String::as_str), word, ""),
+                )
+            }
+            ["export" | "profile" | "records"] => {
+                let tables = self.ctx.get_model().get_tables();
+                let names = tables.iter().map(|t| t.name.as_str());
+                (start, candidates(names, word, " "))
+            }
+            ["export", _, _] => (start, candidates(["--columns"].into_iter(), word, " ")),
+            ["export", table, _, "--columns", ..] => {
+                let value_start = word.rfind(',').map(|i| i + 1).unwrap_or(0);
+                let columns = match self.table(table) {
+                    Ok(t) => t.get_columns().as_slice(),
+                    Err(_) => &[],
+                };
+                (
+                    start + value_start,
+                    candidates(columns.iter().map(String::as_str), &word[value_start..], ""),
+                )
+            }
+            ["set"] => (
+                start,
+                candidates(["format", "collation"].into_iter(), word, " "),
+            ),
+            ["set", "format"] => (start, candidates(FORMATS.into_iter(), word, "")),
+            ["set", "collation"] => (start, candidates(model::COLLATIONS.into_iter(), word, "")),
+            ["bookmark", "apply" | "delete"] => {
+                let names = self.bookmarks.names();
+                (
+                    start,
+                    candidates(names.into_iter().map(String::as_str), word, ""),
+                )
+            }
+            _ => (start, vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table() -> model::Table {
+        let mut t = model::Table::new("t1", vec![String::from("name"), String::from("country")]);
+        for (name, country) in [("ni", "swe"), ("si", "cn"), ("ai", "swe")] {
+            t.insert(vec![
+                model::DataType::from_string(name),
+                model::DataType::from_string(country),
+            ]);
+        }
+        t
+    }
+
+    fn model_of(t: model::Table) -> model::Model {
+        let mut m = model::Model::new();
+        m.add_table(t);
+        m
+    }
+
+    /// the records of the first table associated with the selection
+    fn count(repl: &Repl) -> usize {
+        let t = &repl.ctx.get_model().get_tables()[0];
+        repl.ctx.get_possible_records(t).len()
+    }
+
+    fn bookmarks() -> Bookmarks {
+        let path = std::env::temp_dir().join("repl-test-bookmarks-not-saved.json");
+        let mut bookmarks = Bookmarks::load(&path).unwrap();
+        bookmarks.add("nordic", &[]);
+        bookmarks
+    }
+
+    #[test]
+    fn complete_commands_and_columns() {
+        let t = table();
+        let m = model_of(t);
+        let repl = Repl::new(m.new_data_context(), bookmarks());
+
+        assert_eq!(repl.complete("de", 2), (0, vec![String::from("deselect ")]));
+        assert_eq!(
+            repl.complete("select c", 8),
+            (7, vec![String::from("country=")])
+        );
+        assert_eq!(
+            repl.complete("bookmark apply n", 16),
+            (15, vec![String::from("nordic")])
+        );
+    }
+
+    #[test]
+    fn complete_values() {
+        let t = table();
+        let m = model_of(t);
+        let repl = Repl::new(m.new_data_context(), bookmarks());
+
+        assert_eq!(
+            repl.complete("select country=", 15),
+            (15, vec![String::from("cn"), String::from("swe")])
+        );
+        assert_eq!(
+            repl.complete("select country=cn,s", 19),
+            (18, vec![String::from("swe")])
+        );
+    }
+
+    #[test]
+    fn execute_commands() {
+        let t = table();
+        let m = model_of(t);
+        let mut repl = Repl::new(m.new_data_context(), bookmarks());
+
+        repl.execute("select country=swe,cn").unwrap();
+        assert_eq!(count(&repl), 3);
+        repl.execute("deselect country=cn").unwrap();
+        assert_eq!(count(&repl), 2);
+        repl.execute("back").unwrap();
+        assert_eq!(repl.ctx.get_selection().len(), 2);
+
+        // selecting again or deselecting what isn't selected changes nothing
+        repl.execute("select country=swe,swe").unwrap();
+        assert_eq!(repl.ctx.get_selection().len(), 2);
+        repl.execute("back").unwrap();
+        assert_eq!(repl.ctx.get_selection().len(), 0);
+        repl.execute("deselect country=cn").unwrap();
+        assert_eq!(
+            repl.execute("back").unwrap_err().message,
+            "Nothing to go back to"
+        );
+
+        assert!(repl.execute("select country").is_err());
+        assert!(repl.execute("bogus").is_err());
+    }
+
+    #[test]
+    fn show_dimension_expressions() {
+        let mut t = model::Table::new("t1", vec![String::from("name"), String::from("age")]);
+        for (name, age) in [("ni", "35"), ("si", "34"), ("ai", "1")] {
+            t.insert(vec![
+                model::DataType::from_string(name),
+                model::DataType::from_string(age),
+            ]);
+        }
+        let m = model_of(t);
+        let mut repl = Repl::new(m.new_data_context(), bookmarks());
+        let show = |text: &str| {
+            let (columns, rows) = repl.show(text).unwrap();
+            let rows: Vec<String> = rows.iter().map(|r| format!("{} {}", r[0], r[1])).collect();
+            (columns[0].clone(), rows)
+        };
+
+        assert_eq!(
+            show("class(age, 10)"),
+            (
+                String::from("class(age, 10)"),
+                vec![
+                    String::from("0 <= x < 10 1"),
+                    String::from("30 <= x < 40 2")
+                ]
+            )
+        );
+        assert_eq!(
+            show(" class( age,  10 )  by count desc limit 1").1,
+            vec!["30 <= x < 40 2"]
+        );
+        assert!(repl.execute("show class(age, 10) by count").is_ok());
+        assert!(repl.show("class(age, 10) by").is_err());
+        assert!(repl.show("no such column").is_err());
+    }
+
+    #[test]
+    fn show_null_values() {
+        let mut t = model::Table::new("t1", vec![String::from("name")]);
+        for name in ["ni", "si"] {
+            t.insert(vec![model::DataType::from_string(name)]);
+            t.insert(vec![model::DataType::Null]);
+        }
+        let m = model_of(t);
+        let repl = Repl::new(m.new_data_context(), bookmarks());
+
+        // the null group is shown with and without options
+        let (_, rows) = repl.show("name").unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            vec![model::DataType::Null, model::DataType::Int(2)]
+        );
+        assert_eq!(repl.show("name limit 10").unwrap().1, rows);
+    }
+
+    #[test]
+    fn show_sorted_by_collation() {
+        let mut t = model::Table::new("t1", vec![String::from("name")]);
+        for name in ["b", "A", "a", "C", "b"] {
+            t.insert(vec![model::DataType::from_string(name)]);
+        }
+        let m = model_of(t);
+        let mut repl = Repl::new(m.new_data_context(), bookmarks());
+        let show = |repl: &Repl, text: &str| -> Vec<String> {
+            let (_, rows) = repl.show(text).unwrap();
+            rows.iter().map(|r| format!("{} {}", r[0], r[1])).collect()
+        };
+
+        assert_eq!(show(&repl, "name"), ["A 1", "C 1", "a 1", "b 2"]);
+        assert_eq!(show(&repl, "name by value"), ["A 1", "C 1", "a 1", "b 2"]);
+        assert_eq!(show(&repl, "name by value desc limit 2"), ["b 2", "a 1"]);
+
+        repl.execute("set collation nocase").unwrap();
+        assert_eq!(show(&repl, "name"), ["A 1", "a 1", "b 2", "C 1"]);
+        assert_eq!(show(&repl, "name by value"), ["A 1", "a 1", "b 2", "C 1"]);
+        assert_eq!(show(&repl, "name by value desc limit 2"), ["C 1", "b 2"]);
+        // ties are ordered by the collation too
+        assert_eq!(show(&repl, "name by count offset 1"), ["a 1", "C 1", "b 2"]);
+    }
+
+    #[test]
+    fn select_in_calendar() {
+        let mut t = model::Table::new("sales", vec![String::from("day"), String::from("amount")]);
+        for (day, amount) in [
+            ("2022-12-30", "5"),
+            ("2023-01-02", "7"),
+            ("2023-03-01", "1"),
+        ] {
+            t.insert(vec![
+                model::DataType::from_string(day),
+                model::DataType::from_string(amount),
+            ]);
+        }
+        let mut m = model_of(t);
+        crate::calendar::add_calendars(&mut m, "sales");
+        let mut repl = Repl::new(m.new_data_context(), bookmarks());
+
+        repl.run_batch("select year=2023").unwrap();
+        assert_eq!(count(&repl), 2);
+        let (_, rows) = repl.show("amount").unwrap();
+        let amounts: Vec<String> = rows.iter().map(|r| r[0].to_string()).collect();
+        assert_eq!(amounts, ["1", "7"]);
+        repl.run_batch("clear; select month=3").unwrap();
+        assert_eq!(count(&repl), 1);
+        assert!(repl.execute("records calendar_day").is_ok());
+        assert!(repl.execute("records nope").is_err());
+    }
+
+    #[test]
+    fn export_associated_table() {
+        let mut m = model_of(table());
+        let mut t = model::Table::new("t2", vec![String::from("country"), String::from("capital")]);
+        for (country, capital) in [("swe", "stockholm"), ("cn", "beijing"), ("no", "oslo")] {
+            t.insert(vec![
+                model::DataType::from_string(country),
+                model::DataType::from_string(capital),
+            ]);
+        }
+        m.add_table(t);
+        let mut repl = Repl::new(m.new_data_context(), bookmarks());
+
+        let path =
+            std::env::temp_dir().join(format!("repl-test-export-t2-{}.csv", std::process::id()));
+        let script = format!(
+            "select name=si; export t2 {} --columns capital",
+            path.display()
+        );
+        repl.run_batch(&script).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "capital\nbeijing\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn run_batch_commands() {
+        let t = table();
+        let m = model_of(t);
+        let mut repl = Repl::new(m.new_data_context(), bookmarks());
+
+        let script = "# pick a country\nselect country=swe; show name\n\neval count()\n";
+        repl.run_batch(script).unwrap();
+        assert_eq!(count(&repl), 2);
+        assert_eq!(repl.ctx.get_possible("name").len(), 2);
+
+        let err = repl.run_batch("clear\nshow height; select country=cn").unwrap_err();
+        assert_eq!(err.message, "line 2: show height: No column named height");
+        assert_eq!(repl.ctx.get_selection().len(), 0);
+        assert!(repl.run_batch("eval sum(").is_err());
+
+        repl.run_batch("set format csv; records").unwrap();
+        assert_eq!(repl.format, Format::Csv);
+        assert!(repl.execute("set format xml").is_err());
+        assert!(repl.execute("show name by count desc limit 1").is_ok());
+        assert_eq!(
+            repl.show("name by count desc limit 1").unwrap().1,
+            vec![vec![
+                model::DataType::from_string("ai"),
+                model::DataType::Int(1)
+            ]]
+        );
+        assert!(repl.execute("show name by count limit x").is_err());
+        assert!(repl.execute("show name by").is_err());
+        assert!(repl.execute("show name offset 1 by sum(").is_err());
+        assert!(repl.execute("show histogram(name,2)").is_ok());
+        assert!(repl.execute("show class(name,0)").is_err());
+        assert!(repl.execute("profile t1").is_ok());
+        assert!(repl.execute("profile t2").is_err());
+        repl.execute("set collation nocase").unwrap();
+        assert_eq!(repl.collation, model::Collation::CaseInsensitive);
+        assert!(repl.execute("set collation de").is_err());
+
+        let path =
+            std::env::temp_dir().join(format!("repl-test-export-{}.csv", std::process::id()));
+        let script = format!(
+            "select country=cn; export t1 {} --columns name",
+            path.display()
+        );
+        repl.run_batch(&script).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "name\nsi\n");
+        std::fs::remove_file(path).unwrap();
+        assert!(repl.execute("export t2 out.csv").is_err());
+    }
+}
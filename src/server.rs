@@ -0,0 +1,537 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use crate::ast::Expression;
+use crate::json::JsonValue;
+use crate::model::{DataType, Model, ModelContext, Selection};
+use crate::websocket;
+
+pub fn message(kind: &str, members: Vec<(&str, JsonValue)>) -> JsonValue {
+    let mut object = vec![(String::from("type"), JsonValue::String(String::from(kind)))];
+    object.extend(members.into_iter().map(|(k, v)| (String::from(k), v)));
+    JsonValue::Object(object)
+}
+
+pub fn error(text: &str) -> JsonValue {
+    message(
+        "error",
+        vec![("message", JsonValue::String(String::from(text)))],
+    )
+}
+
+fn string(s: &str) -> JsonValue {
+    JsonValue::String(String::from(s))
+}
+
+fn selection_json(s: &Selection) -> JsonValue {
+    JsonValue::Object(vec![
+        (String::from("column"), string(&s.column)),
+        (String::from("value"), string(&s.value)),
+    ])
+}
+
+fn eval_json(ctx: &ModelContext, expr: &Expression) -> (&'static str, JsonValue) {
+    match ctx.eval(expr) {
+        Ok(value) => ("value", JsonValue::from(&value)),
+        Err(e) => ("error", string(&e.message)),
+    }
+}
+
+/// reads selections from an object with a column
+/// and either a single value or a list of values
+pub fn selections_from_json(msg: &JsonValue) -> Option<Vec<Selection>> {
+    let column = msg.get("column").and_then(JsonValue::as_str)?;
+    let values: Vec<&str> = match (msg.get("value"), msg.get("values")) {
+        (Some(value), _) => vec![value.as_str()?],
+        (None, Some(values)) => values
+            .as_array()?
+            .iter()
+            .filter_map(JsonValue::as_str)
+            .collect(),
+        (None, None) => return None,
+    };
+    Some(
+        values
+            .into_iter()
+            .map(|v| Selection {
+                column: String::from(column),
+                value: String::from(v),
+            })
+            .collect(),
+    )
+}
+
+/// the state of one client connection: its own selections,
+/// the expressions it watches and the fields it follows
+pub struct Session<'a> {
+    ctx: ModelContext<'a>,
+    watches: Vec<(String, Expression)>,
+    fields: Option<Vec<String>>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(model: &'a Model) -> Session<'a> {
+        Session {
+            ctx: model.new_data_context(),
+            watches: vec![],
+            fields: None,
+        }
+    }
+
+    /// handles a client message, returning the messages to send back
+    pub fn handle(&mut self, text: &str) -> Vec<JsonValue> {
+        let msg = match JsonValue::from_string(text) {
+            Ok(msg) => msg,
+            Err(e) => return vec![error(&format!("invalid message: {}", e))],
+        };
+
+        let kind = msg.get("type").and_then(JsonValue::as_str).unwrap_or("");
+        let changed = match kind {
+            "select" | "deselect" => {
+                let selections = match selections_from_json(&msg) {
+                    Some(selections) => selections,
+                    None => return vec![error("expected column and value or values")],
+                };
+                let mut state = self.ctx.get_selection().clone();
+                if kind == "select" {
+                    state.extend(selections);
+                } else {
+                    state.retain(|s| !selections.contains(s));
+                }
+                self.ctx.apply(&state);
+                true
+            }
+            "clear" => {
+                self.ctx.clear();
+                true
+            }
+            "back" => self.ctx.back(),
+            "forward" => self.ctx.forward(),
+            "watch" => {
+                let id = msg.get("id").and_then(JsonValue::as_str);
+                let expr = msg.get("expression").and_then(JsonValue::as_str);
+                let (id, expr) = match (id, expr) {
+                    (Some(id), Some(expr)) => (id, expr),
+                    _ => return vec![error("expected id and expression")],
+                };
+                let expr = match Expression::from_string(expr) {
+                    Ok(expr) => expr,
+                    Err(e) => return vec![error(e.message)],
+                };
+                self.watches.retain(|(w, _)| w != id);
+                self.watches.push((String::from(id), expr));
+                true
+            }
+            "unwatch" => {
+                let id = msg.get("id").and_then(JsonValue::as_str).unwrap_or("");
+                self.watches.retain(|(w, _)| w != id);
+                false
+            }
+            "fields" => {
+                let columns = msg
+                    .get("columns")
+                    .and_then(JsonValue::as_array)
+                    .map(|cols| {
+                        cols.iter()
+                            .filter_map(JsonValue::as_str)
+                            .map(String::from)
+                            .collect()
+                    });
+                self.fields = columns;
+                true
+            }
+            "eval" => {
+                let text = msg
+                    .get("expression")
+                    .and_then(JsonValue::as_str)
+                    .unwrap_or("");
+                let expr = match Expression::from_string(text) {
+                    Ok(expr) => expr,
+                    Err(e) => return vec![error(e.message)],
+                };
+                let (key, value) = eval_json(&self.ctx, &expr);
+                return vec![message(
+                    "result",
+                    vec![("expression", string(text)), (key, value)],
+                )];
+            }
+            _ => return vec![error(&format!("unknown message type '{}'", kind))],
+        };
+
+        match changed {
+            true => vec![self.update()],
+            false => vec![],
+        }
+    }
+
+    /// the current selection, watch results and field value states
+    pub fn update(&self) -> JsonValue {
+        let selection = self
+            .ctx
+            .get_selection()
+            .iter()
+            .map(selection_json)
+            .collect();
+
+        let watches = self
+            .watches
+            .iter()
+            .map(|(id, expr)| {
+                let (key, value) = eval_json(&self.ctx, expr);
+                JsonValue::Object(vec![
+                    (String::from("id"), string(id)),
+                    (
+                        String::from("expression"),
+                        JsonValue::String(expr.to_string()),
+                    ),
+                    (String::from(key), value),
+                ])
+            })
+            .collect();
+
+        let columns: Vec<String> = match &self.fields {
+            Some(columns) => columns.clone(),
+            None => self
+                .ctx
+                .get_model()
+                .get_columns()
+                .into_iter()
+                .cloned()
+                .collect(),
+        };
+        let fields = columns
+            .iter()
+            .map(|col| {
+                JsonValue::Object(vec![
+                    (String::from("column"), string(col)),
+                    (
+                        String::from("values"),
+                        JsonValue::Array(self.field_values(col)),
+                    ),
+                ])
+            })
+            .collect();
+
+        message(
+            "update",
+            vec![
+                ("selection", JsonValue::Array(selection)),
+                ("watches", JsonValue::Array(watches)),
+                ("fields", JsonValue::Array(fields)),
+            ],
+        )
+    }
+
+    fn field_values(&self, col: &str) -> Vec<JsonValue> {
+        let selected = self.ctx.get_selected(col);
+        let possible = self.ctx.get_possible(col);
+
+        let state = |v: &DataType| {
+            if selected.contains(&v) {
+                "selected"
+            } else if possible.contains(&v) {
+                "possible"
+            } else {
+                "excluded"
+            }
+        };
+
+        self.ctx
+            .get_model()
+            .get_all_values(col)
+            .into_iter()
+            .map(|v| {
+                JsonValue::Object(vec![
+                    (String::from("value"), JsonValue::from(v)),
+                    (String::from("state"), string(state(v))),
+                ])
+            })
+            .collect()
+    }
+}
+
+/// serves the model to websocket clients, each connection
+/// runs on its own thread with its own selections
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    pub fn bind(addr: &str) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// accepts connections until the listener fails
+    pub fn run(&self, model: Arc<Model>) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            let model = model.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(e) = serve_connection(stream, &model) {
+                    println!("connection {:?} closed: {}", peer, e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+fn serve_connection(stream: TcpStream, model: &Model) -> io::Result<()> {
+    let mut stream = websocket::Duplex {
+        reader: io::BufReader::new(stream.try_clone()?),
+        writer: stream,
+    };
+    websocket::accept(&mut stream)?;
+
+    let send = |stream: &mut websocket::Duplex<_, TcpStream>, msg: &JsonValue| {
+        websocket::write_frame(
+            stream,
+            websocket::Opcode::Text,
+            msg.to_string().as_bytes(),
+            None,
+        )
+    };
+
+    let mut session = Session::new(model);
+    send(&mut stream, &session.update())?;
+
+    loop {
+        let text = match websocket::read_message(&mut stream)? {
+            websocket::Message::Text(text) => text,
+            websocket::Message::Binary(_) => {
+                send(&mut stream, &error("binary messages are not supported"))?;
+                continue;
+            }
+            websocket::Message::Close => {
+                websocket::write_frame(&mut stream, websocket::Opcode::Close, &[], None)?;
+                return Ok(());
+            }
+        };
+
+        for reply in session.handle(&text) {
+            send(&mut stream, &reply)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::fixture_model;
+
+    fn value_states(update: &JsonValue, column: &str) -> Vec<(String, String)> {
+        let fields = update.get("fields").and_then(JsonValue::as_array).unwrap();
+        let field = fields
+            .iter()
+            .find(|f| f.get("column").and_then(JsonValue::as_str) == Some(column))
+            .unwrap();
+        field
+            .get("values")
+            .and_then(JsonValue::as_array)
+            .unwrap()
+            .iter()
+            .map(|v| {
+                (
+                    v.get("value").unwrap().to_string(),
+                    String::from(v.get("state").and_then(JsonValue::as_str).unwrap()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn session_select_and_watch() {
+        let model = fixture_model();
+        let mut session = Session::new(&model);
+
+        let replies = session.handle(r#"{"type": "watch", "id": "a", "expression": "sum(age)"}"#);
+        let watches = replies[0].get("watches").unwrap().to_string();
+        assert_eq!(
+            watches,
+            r#"[{"id":"a","expression":"sum(age)","value":70.5}]"#
+        );
+
+        let replies =
+            session.handle(r#"{"type": "select", "column": "country", "values": ["swe"]}"#);
+        let watches = replies[0].get("watches").unwrap().to_string();
+        assert_eq!(
+            watches,
+            r#"[{"id":"a","expression":"sum(age)","value":36.5}]"#
+        );
+        assert_eq!(
+            value_states(&replies[0], "country"),
+            vec![
+                (String::from("\"cn\""), String::from("excluded")),
+                (String::from("\"swe\""), String::from("selected")),
+            ]
+        );
+        assert_eq!(
+            value_states(&replies[0], "name"),
+            vec![
+                (String::from("\"ai\""), String::from("possible")),
+                (String::from("\"ni\""), String::from("possible")),
+                (String::from("\"si\""), String::from("excluded")),
+            ]
+        );
+
+        let replies = session.handle(r#"{"type": "back"}"#);
+        let watches = replies[0].get("watches").unwrap().to_string();
+        assert_eq!(
+            watches,
+            r#"[{"id":"a","expression":"sum(age)","value":70.5}]"#
+        );
+
+        let replies = session.handle(r#"{"type": "eval", "expression": "count()"}"#);
+        assert_eq!(
+            replies[0].to_string(),
+            r#"{"type":"result","expression":"count()","value":3}"#
+        );
+
+        let replies = session.handle(r#"{"type": "nope"}"#);
+        assert_eq!(
+            replies[0].get("type").and_then(JsonValue::as_str),
+            Some("error")
+        );
+    }
+
+    type Client = websocket::Duplex<io::BufReader<TcpStream>, TcpStream>;
+
+    fn connect(addr: SocketAddr) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut client = websocket::Duplex {
+            reader: io::BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        };
+        websocket::connect(&mut client, &addr.to_string(), "/").unwrap();
+        client
+    }
+
+    fn send(client: &mut Client, text: &str) {
+        websocket::write_frame(
+            client,
+            websocket::Opcode::Text,
+            text.as_bytes(),
+            Some([7, 1, 9, 3]),
+        )
+        .unwrap();
+    }
+
+    fn receive(client: &mut Client) -> JsonValue {
+        match websocket::read_message(client).unwrap() {
+            websocket::Message::Text(text) => JsonValue::from_string(&text).unwrap(),
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn serve_loopback_clients() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let model = Arc::new(fixture_model());
+        thread::spawn(move || server.run(model));
+
+        let mut c1 = connect(addr);
+        let mut c2 = connect(addr);
+        assert_eq!(
+            receive(&mut c1).get("type").and_then(JsonValue::as_str),
+            Some("update")
+        );
+        assert_eq!(
+            receive(&mut c2).get("type").and_then(JsonValue::as_str),
+            Some("update")
+        );
+
+        send(
+            &mut c1,
+            r#"{"type": "watch", "id": "n", "expression": "count()"}"#,
+        );
+        receive(&mut c1);
+        send(
+            &mut c1,
+            r#"{"type": "select", "column": "country", "value": "cn"}"#,
+        );
+        let update = receive(&mut c1);
+        assert_eq!(
+            update.get("watches").unwrap().to_string(),
+            r#"[{"id":"n","expression":"count()","value":1}]"#
+        );
+
+        // the second connection has its own selections
+        send(&mut c2, r#"{"type": "eval", "expression": "sum(age)"}"#);
+        assert_eq!(
+            receive(&mut c2).get("value"),
+            Some(&JsonValue::Number(String::from("70.5")))
+        );
+
+        websocket::write_frame(&mut c1, websocket::Opcode::Close, &[], Some([1, 2, 3, 4])).unwrap();
+        assert_eq!(
+            websocket::read_message(&mut c1).unwrap(),
+            websocket::Message::Close
+        );
+    }
+
+    #[test]
+    fn serve_deeply_nested_message() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let model = Arc::new(fixture_model());
+        thread::spawn(move || server.run(model));
+        let error_type =
+            |msg: &JsonValue| msg.get("type").and_then(JsonValue::as_str) == Some("error");
+
+        let mut c1 = connect(addr);
+        receive(&mut c1);
+        send(&mut c1, &"[".repeat(200_000));
+        assert!(error_type(&receive(&mut c1)));
+        let expr = "isnull(".repeat(50_000);
+        send(
+            &mut c1,
+            &format!(r#"{{"type": "eval", "expression": "{}"}}"#, expr),
+        );
+        assert!(error_type(&receive(&mut c1)));
+
+        // the connection and the server are still up
+        send(&mut c1, r#"{"type": "eval", "expression": "count()"}"#);
+        assert_eq!(
+            receive(&mut c1).get("value"),
+            Some(&JsonValue::Number(String::from("3")))
+        );
+        let mut c2 = connect(addr);
+        assert!(!error_type(&receive(&mut c2)));
+    }
+
+    #[test]
+    fn serve_padded_expressions() {
+        let server = Server::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let model = Arc::new(fixture_model());
+        thread::spawn(move || server.run(model));
+
+        let mut client = connect(addr);
+        receive(&mut client);
+        send(
+            &mut client,
+            r#"{"type": "watch", "id": "a", "expression": "sum( "}"#,
+        );
+        let reply = receive(&mut client);
+        assert_eq!(reply.get("type").and_then(JsonValue::as_str), Some("error"));
+        send(&mut client, r#"{"type": "eval", "expression": "add(1, "}"#);
+        let reply = receive(&mut client);
+        assert_eq!(reply.get("type").and_then(JsonValue::as_str), Some("error"));
+
+        send(&mut client, r#"{"type": "eval", "expression": "count() "}"#);
+        assert_eq!(
+            receive(&mut client).get("value"),
+            Some(&JsonValue::Number(String::from("3")))
+        );
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::Path;
+
+use crate::checksum::crc32;
+use crate::model::{Column, DataType, Model, Record, Table, Value};
+
+const MAGIC: &[u8; 4] = b"DTSN";
+/// bumped whenever the encoding changes, 2 added bools and nulls
+/// and 3 dates and timestamps
+const VERSION: u16 = 3;
+
+const TAG_STRING: u8 = 0;
+const TAG_INT: u8 = 1;
+const TAG_DECIMAL: u8 = 2;
+const TAG_BOOL: u8 = 3;
+const TAG_NULL: u8 = 4;
+const TAG_DATE: u8 = 5;
+const TAG_TIMESTAMP: u8 = 6;
+
+#[derive(Debug)]
+pub struct SnapshotError {
+    pub message: String,
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+fn invalid(message: &str) -> SnapshotError {
+    SnapshotError {
+        message: format!("invalid snapshot: {}", message),
+    }
+}
+
+fn write_uvarint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn write_varint(out: &mut Vec<u8>, v: i64) {
+    write_uvarint(out, ((v << 1) ^ (v >> 63)) as u64);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_uvarint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_value(out: &mut Vec<u8>, v: &DataType) {
+    match v {
+        DataType::String(s) => {
+            out.push(TAG_STRING);
+            write_str(out, s);
+        }
+        DataType::Int(i) => {
+            out.push(TAG_INT);
+            write_varint(out, *i);
+        }
+        DataType::Decimal(n, p) => {
+            out.push(TAG_DECIMAL);
+            write_varint(out, *n);
+            out.push(*p);
+        }
+        DataType::Bool(b) => {
+            out.push(TAG_BOOL);
+            out.push(*b as u8);
+        }
+        DataType::Date(days) => {
+            out.push(TAG_DATE);
+            write_varint(out, *days as i64);
+        }
+        DataType::Timestamp(seconds) => {
+            out.push(TAG_TIMESTAMP);
+            write_varint(out, *seconds);
+        }
+        DataType::Null => out.push(TAG_NULL),
+    }
+}
+
+fn write_table(out: &mut Vec<u8>, table: &Table) {
+    write_str(out, &table.name);
+    write_uvarint(out, table.columns.len() as u64);
+    for c in &table.columns {
+        write_str(out, c);
+    }
+
+    write_uvarint(out, table.records.len() as u64);
+    for record in &table.records {
+        for v in record {
+            write_value(out, v);
+        }
+    }
+
+    // sorted so that the same model always gives the same bytes
+    let mut index = table.get_index();
+    index.sort();
+    write_uvarint(out, index.len() as u64);
+    for (column, value, rows) in index {
+        let position = table
+            .get_col_index(column)
+            .expect("indexed column should exist");
+        // the value is the display form of the column in its first
+        // row, so it is not stored again
+        debug_assert_eq!(&table.records[rows[0]][position].to_string(), value);
+        write_uvarint(out, position as u64);
+        write_uvarint(out, rows.len() as u64);
+        // row ids are ascending, store the gaps between them
+        let mut previous = 0;
+        for row in rows {
+            write_uvarint(out, (row - previous) as u64);
+            previous = *row;
+        }
+    }
+}
+
+/// encodes a model, its tables, typed values and indexes
+///
+/// layout: magic, version (u16 le), table count, tables,
+/// crc-32 (u32 le) of everything before it. integers are
+/// LEB128 varints, signed ones zigzag encoded
+pub fn to_bytes(model: &Model) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    write_uvarint(&mut out, model.get_tables().len() as u64);
+    for table in model.get_tables() {
+        write_table(&mut out, table);
+    }
+    let checksum = crc32(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        let b = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| invalid("unexpected end of data"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn uvarint(&mut self) -> Result<u64, SnapshotError> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            v |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    fn varint(&mut self) -> Result<i64, SnapshotError> {
+        let v = self.uvarint()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    /// a length, checked against the remaining bytes so that a
+    /// corrupt count cannot cause a huge allocation
+    fn len(&mut self) -> Result<usize, SnapshotError> {
+        let n = self.uvarint()? as usize;
+        if n > self.bytes.len() - self.pos {
+            return Err(invalid("length out of range"));
+        }
+        Ok(n)
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let n = self.len()?;
+        let s = std::str::from_utf8(&self.bytes[self.pos..self.pos + n])
+            .map_err(|_| invalid("string is not utf-8"))?;
+        self.pos += n;
+        Ok(String::from(s))
+    }
+
+    fn value(&mut self) -> Result<DataType, SnapshotError> {
+        match self.byte()? {
+            TAG_STRING => Ok(DataType::String(self.string()?.into())),
+            TAG_INT => Ok(DataType::Int(self.varint()?)),
+            TAG_DECIMAL => {
+                let n = self.varint()?;
+                Ok(DataType::Decimal(n, self.byte()?))
+            }
+            TAG_BOOL => Ok(DataType::Bool(self.byte()? != 0)),
+            TAG_NULL => Ok(DataType::Null),
+            TAG_DATE => {
+                let days =
+                    i32::try_from(self.varint()?).map_err(|_| invalid("date out of range"))?;
+                Ok(DataType::Date(days))
+            }
+            TAG_TIMESTAMP => Ok(DataType::Timestamp(self.varint()?)),
+            tag => Err(invalid(&format!("unknown value tag {}", tag))),
+        }
+    }
+
+    fn table(&mut self) -> Result<Table, SnapshotError> {
+        let name = self.string()?;
+        let num_columns = self.len()?;
+        let columns = (0..num_columns)
+            .map(|_| self.string())
+            .collect::<Result<Vec<Column>, _>>()?;
+
+        let num_records = self.len()?;
+        let mut records: Vec<Record> = Vec::with_capacity(num_records);
+        for _ in 0..num_records {
+            records.push(
+                (0..num_columns)
+                    .map(|_| self.value())
+                    .collect::<Result<Record, _>>()?,
+            );
+        }
+
+        let num_entries = self.len()?;
+        let mut index: Vec<(Column, Value, Vec<usize>)> = Vec::with_capacity(num_entries);
+        for _ in 0..num_entries {
+            let position = self.uvarint()? as usize;
+            let column = columns
+                .get(position)
+                .ok_or_else(|| invalid("index column out of range"))?
+                .clone();
+            let num_rows = self.len()?;
+            if num_rows == 0 {
+                return Err(invalid("empty index entry"));
+            }
+            let mut rows = Vec::with_capacity(num_rows);
+            let mut row: usize = 0;
+            for _ in 0..num_rows {
+                row = row.saturating_add(self.uvarint()? as usize);
+                if row >= records.len() {
+                    return Err(invalid("index row out of range"));
+                }
+                rows.push(row);
+            }
+            let value = records[rows[0]][position].to_string();
+            index.push((column, value, rows));
+        }
+
+        Ok(Table::from_parts(&name, columns, records, index))
+    }
+}
+
+/// decodes a model written by `to_bytes`, validating
+/// the magic, version and checksum
+pub fn from_bytes(bytes: &[u8]) -> Result<Model, SnapshotError> {
+    if bytes.len() < MAGIC.len() + 2 + 4 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not a snapshot file"));
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version < VERSION {
+        return Err(invalid(&format!(
+            "version {} is older than version {}, save the snapshot again",
+            version, VERSION
+        )));
+    }
+    if version > VERSION {
+        return Err(invalid(&format!(
+            "version {} is newer than version {}, which is the latest supported",
+            version, VERSION
+        )));
+    }
+
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    if crc32(body) != checksum {
+        return Err(invalid("checksum mismatch"));
+    }
+
+    let mut r = Reader {
+        bytes: body,
+        pos: MAGIC.len() + 2,
+    };
+    let mut model = Model::new();
+    for _ in 0..r.len()? {
+        model.add_table(r.table()?);
+    }
+    if r.pos != body.len() {
+        return Err(invalid("trailing data"));
+    }
+    Ok(model)
+}
+
+/// writes a snapshot of the model to a file
+pub fn save(model: &Model, path: &Path) -> Result<(), SnapshotError> {
+    fs::write(path, to_bytes(model)).map_err(|e| SnapshotError {
+        message: format!("failed to write {}: {}", path.display(), e),
+    })
+}
+
+/// loads a model from a snapshot file
+pub fn load(path: &Path) -> Result<Model, SnapshotError> {
+    let bytes = fs::read(path).map_err(|e| SnapshotError {
+        message: format!("failed to read {}: {}", path.display(), e),
+    })?;
+    from_bytes(&bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fixture::fixture_model;
+    use crate::model::Selection;
+
+    /// the shared model, with a table of every kind of value
+    /// and an empty one
+    fn snapshot_model() -> Model {
+        let mut model = fixture_model();
+        let mut values = Table::new("values", vec![String::from("name"), String::from("value")]);
+        for (name, value) in [
+            ("ni", "35"),
+            ("si", "-34"),
+            ("ai", "1.50"),
+            ("åsa", "x"),
+            ("bo", "2023-05-31"),
+            ("ek", "1969-12-31 23:59"),
+        ] {
+            values.insert(vec![
+                DataType::from_string(name),
+                DataType::from_string(value),
+            ]);
+        }
+        model.add_table(values);
+        model.add_table(Table::new("empty", vec![String::from("name")]));
+        model
+    }
+
+    #[test]
+    fn round_trip() {
+        let model = snapshot_model();
+        let bytes = to_bytes(&model);
+        let loaded = from_bytes(&bytes).unwrap();
+
+        assert_eq!(to_bytes(&loaded), bytes);
+        for table in model.get_tables() {
+            assert_eq!(
+                loaded.get_table(&table.name).unwrap().records,
+                table.records
+            );
+        }
+        let values = loaded.get_table("values").unwrap();
+        assert_eq!(values.records[2][1], DataType::Decimal(150, 2));
+        assert_eq!(values.records[5][1], DataType::Timestamp(-60));
+
+        let possible = values.get_possible(&[Selection {
+            column: String::from("name"),
+            value: String::from("åsa"),
+        }]);
+        assert_eq!(possible.len(), 1);
+        assert_eq!(possible[0][1], DataType::String("x".into()));
+        assert!(loaded.get_table("empty").unwrap().records.is_empty());
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        match from_bytes(bytes) {
+            Ok(_) => panic!("expected the snapshot to be rejected"),
+            Err(e) => e.message,
+        }
+    }
+
+    #[test]
+    fn reject_corrupt_snapshots() {
+        let mut bytes = to_bytes(&snapshot_model());
+
+        let last = bytes.len() - 5;
+        bytes[last] ^= 1;
+        assert!(error(&bytes).contains("checksum"));
+
+        assert!(from_bytes(&bytes[..bytes.len() - 10]).is_err());
+        assert!(from_bytes(b"a,b\n1,2\n").is_err());
+
+        bytes[4] = 9;
+        assert!(error(&bytes).contains("version 9 is newer"));
+        bytes[4] = 1;
+        assert!(error(&bytes).contains("version 1 is older"));
+    }
+}
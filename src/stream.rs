@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{EvalError, Expression, Function, GroupRow};
+use crate::model::{Column, Columns, DataType, Record};
+
+#[derive(Debug)]
+pub struct StreamError {
+    pub message: String,
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<EvalError> for StreamError {
+    fn from(e: EvalError) -> StreamError {
+        StreamError { message: e.message }
+    }
+}
+
+/// the running value of an aggregate expression for one group
+#[derive(Debug)]
+enum State {
+    Const,
+    Count(i64),
+    Sum(DataType),
+    Distinct(BTreeSet<DataType>),
+    Add(Box<State>, Box<State>),
+    Call(Vec<State>),
+}
+
+impl State {
+    fn new(expr: &Expression) -> Result<State, StreamError> {
+        Ok(match expr {
+            Expression::StrConst(_) | Expression::IntConst(_) | Expression::DecConst(_, _) => {
+                State::Const
+            }
+            Expression::Count => State::Count(0),
+            Expression::Sum(_) => State::Sum(DataType::Int(0)),
+            Expression::Variable(_) | Expression::Values(_) => State::Distinct(BTreeSet::new()),
+            Expression::Add(a, b) => State::Add(Box::new(State::new(a)?), Box::new(State::new(b)?)),
+            Expression::Call(_, args) => {
+                State::Call(args.iter().map(State::new).collect::<Result<_, _>>()?)
+            }
+            Expression::Class(_, _, _) | Expression::Histogram(_, _) => {
+                return Err(expr.dimension_error().into())
+            }
+        })
+    }
+
+    fn update(
+        &mut self,
+        expr: &Expression,
+        columns: &Columns,
+        record: &Record,
+        max_values: usize,
+    ) -> Result<(), StreamError> {
+        match (self, expr) {
+            (State::Const, _) => (),
+            (State::Count(n), _) => *n += 1,
+            (State::Sum(total), Expression::Sum(e)) => {
+                let value = e.eval_record(columns, record)?;
+                *total = DataType::sum(std::mem::replace(total, DataType::Null), value)
+                    .map_err(|e| expr.arithmetic_error(e))?;
+            }
+            (State::Distinct(values), Expression::Variable(col) | Expression::Values(col)) => {
+                let value = &record[Expression::col_index(columns, col)?];
+                if !values.contains(value) {
+                    if values.len() == max_values {
+                        return Err(StreamError {
+                            message: format!(
+                                "{} has more than {} values in a group",
+                                col, max_values
+                            ),
+                        });
+                    }
+                    values.insert(value.clone());
+                }
+            }
+            (State::Add(a, b), Expression::Add(ea, eb)) => {
+                a.update(ea, columns, record, max_values)?;
+                b.update(eb, columns, record, max_values)?;
+            }
+            (State::Call(states), Expression::Call(_, args)) => {
+                for (state, arg) in states.iter_mut().zip(args) {
+                    state.update(arg, columns, record, max_values)?;
+                }
+            }
+            (state, _) => return Err(state.mismatch(expr)),
+        }
+        Ok(())
+    }
+
+    /// the value of the expression over the records of the group,
+    /// the same as `Expression::eval` gives for them
+    fn finish(self, expr: &Expression) -> Result<DataType, StreamError> {
+        match (self, expr) {
+            (State::Const, Expression::StrConst(s)) => Ok(DataType::String(s.as_str().into())),
+            (State::Const, Expression::IntConst(i)) => Ok(DataType::Int(*i)),
+            (State::Const, Expression::DecConst(v, p)) => Ok(DataType::Decimal(*v, *p)),
+            (State::Count(n), _) => Ok(DataType::Int(n)),
+            (State::Sum(total), _) => Ok(total),
+            (State::Distinct(values), Expression::Values(_)) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                Ok(DataType::String(values.join(", ").into()))
+            }
+            (State::Distinct(values), Expression::Variable(col)) => match values.len() {
+                1 => Ok(values.into_iter().next().unwrap()),
+                n => Err(StreamError {
+                    message: format!("{} has {} possible values", col, n),
+                }),
+            },
+            (State::Add(a, b), Expression::Add(ea, eb)) => {
+                DataType::sum(a.finish(ea)?, b.finish(eb)?)
+                    .map_err(|e| expr.arithmetic_error(e).into())
+            }
+            (State::Call(states), Expression::Call(f @ (Function::If | Function::Case), args)) => {
+                // only the branch taken is finished, as eval does
+                let mut states: Vec<Option<State>> = states.into_iter().map(Some).collect();
+                let mut finish = |i: usize| match states.get_mut(i).and_then(Option::take) {
+                    Some(state) => state.finish(&args[i]),
+                    None => Err(StreamError {
+                        message: format!("no state for argument {} of {}", i + 1, expr),
+                    }),
+                };
+                match f.branch(args.len(), |i| finish(i).map(|v| v.is_true()))? {
+                    Some(i) => finish(i),
+                    None => Ok(DataType::Null),
+                }
+            }
+            (State::Call(states), Expression::Call(function, args)) => {
+                let mut values = vec![];
+                for (state, arg) in states.into_iter().zip(args) {
+                    values.push(state.finish(arg)?);
+                }
+                function
+                    .apply(&values)
+                    .map_err(|e| expr.arithmetic_error(e).into())
+            }
+            (state, _) => Err(state.mismatch(expr)),
+        }
+    }
+
+    /// the error for a state that was not made by `State::new` for
+    /// the expression
+    fn mismatch(&self, expr: &Expression) -> StreamError {
+        StreamError {
+            message: format!("state {:?} does not match {}", self, expr),
+        }
+    }
+}
+
+/// group by aggregation over records that are pushed one at a
+/// time, only the running state of each group is kept, so memory
+/// depends on the number of groups and not the number of records
+///
+/// the number of groups, and of distinct values kept for
+/// `values()` and plain column expressions in a group, is
+/// limited to `max_groups`
+pub struct Aggregation {
+    columns: Columns,
+    filter: Option<Expression>,
+    dims: Vec<usize>,
+    exprs: Vec<Expression>,
+    groups: BTreeMap<Vec<DataType>, Vec<State>>,
+    max_groups: usize,
+}
+
+impl Aggregation {
+    pub fn new(
+        columns: &Columns,
+        dims: &[Column],
+        filter: Option<Expression>,
+        exprs: Vec<Expression>,
+        max_groups: usize,
+    ) -> Result<Aggregation, StreamError> {
+        let mut dim_indices = vec![];
+        for dim in dims {
+            dim_indices.push(Expression::col_index(columns, dim)?);
+        }
+        for expr in filter.iter().chain(exprs.iter()) {
+            for col in expr.get_variables() {
+                Expression::col_index(columns, col)?;
+            }
+        }
+
+        // also checks that every expression can be aggregated
+        let states = exprs
+            .iter()
+            .map(State::new)
+            .collect::<Result<Vec<State>, _>>()?;
+        let mut groups = BTreeMap::new();
+        // without dimensions there is always exactly one group
+        if dims.is_empty() {
+            groups.insert(vec![], states);
+        }
+        Ok(Aggregation {
+            columns: columns.clone(),
+            filter,
+            dims: dim_indices,
+            exprs,
+            groups,
+            max_groups,
+        })
+    }
+
+    /// adds a record to its group, if it passes the filter
+    pub fn push(&mut self, record: &Record) -> Result<(), StreamError> {
+        if let Some(filter) = &self.filter {
+            if !filter.eval_record(&self.columns, record)?.is_true() {
+                return Ok(());
+            }
+        }
+
+        let key: Vec<DataType> = self.dims.iter().map(|i| record[*i].clone()).collect();
+        if !self.groups.contains_key(&key) {
+            if self.groups.len() == self.max_groups {
+                return Err(StreamError {
+                    message: format!("more than {} groups", self.max_groups),
+                });
+            }
+            let states = self
+                .exprs
+                .iter()
+                .map(State::new)
+                .collect::<Result<_, _>>()?;
+            self.groups.insert(key.clone(), states);
+        }
+
+        let states = self.groups.get_mut(&key).expect("group was just inserted");
+        for (state, expr) in states.iter_mut().zip(&self.exprs) {
+            state.update(expr, &self.columns, record, self.max_groups)?;
+        }
+        Ok(())
+    }
+
+    /// the value of each expression per group, ordered by group
+    pub fn finish(self) -> Result<Vec<GroupRow>, StreamError> {
+        let mut rows = vec![];
+        for (key, states) in self.groups {
+            let mut values = vec![];
+            for (state, expr) in states.into_iter().zip(&self.exprs) {
+                values.push(state.finish(expr)?);
+            }
+            rows.push((key, values));
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ast::{group_by, Sort};
+
+    #[test]
+    fn stream_matches_group_by() {
+        let columns = vec![String::from("country"), String::from("age")];
+        let records: Vec<Record> = vec![
+            vec![DataType::from_string("swe"), DataType::from_string("35")],
+            vec![DataType::from_string("cn"), DataType::from_string("34")],
+            vec![DataType::from_string("swe"), DataType::from_string("1.5")],
+            vec![DataType::from_string("swe"), DataType::Null],
+        ];
+        let refs: Vec<&Record> = records.iter().collect();
+        // age has several values in a group, but the branch isn't taken
+        let exprs: Vec<Expression> = [
+            "sum(age)",
+            "add(count(), 1)",
+            "values(age)",
+            "country",
+            "if(count(), count(), age)",
+        ]
+        .iter()
+        .map(|s| Expression::from_string(s).unwrap())
+        .collect();
+        let dims = [String::from("country")];
+
+        let mut aggregation = Aggregation::new(&columns, &dims, None, exprs.clone(), 10).unwrap();
+        for record in &records {
+            aggregation.push(record).unwrap();
+        }
+        assert_eq!(
+            aggregation.finish().unwrap(),
+            group_by(&columns, &refs, &dims, &exprs, &Sort::default()).unwrap()
+        );
+
+        let filter = Expression::from_string("age").ok();
+        let mut aggregation =
+            Aggregation::new(&columns, &[], filter, vec![Expression::Count], 10).unwrap();
+        for record in &records {
+            aggregation.push(record).unwrap();
+        }
+        assert_eq!(
+            aggregation.finish().unwrap(),
+            vec![(vec![], vec![DataType::Int(3)])]
+        );
+
+        let filter = Expression::from_string("gt(age, 30)").ok();
+        let mut aggregation =
+            Aggregation::new(&columns, &[], filter, vec![Expression::Count], 10).unwrap();
+        for record in &records {
+            aggregation.push(record).unwrap();
+        }
+        assert_eq!(
+            aggregation.finish().unwrap(),
+            vec![(vec![], vec![DataType::Int(2)])]
+        );
+    }
+
+    #[test]
+    fn mismatched_state() {
+        let columns = vec![String::from("age")];
+        let record = vec![DataType::Int(1)];
+        let sum = Expression::from_string("sum(age)").unwrap();
+        let mut state = State::new(&sum).unwrap();
+        assert!(state.update(&sum, &columns, &record, 10).is_ok());
+        assert!(state
+            .update(&Expression::Count, &columns, &record, 10)
+            .is_err());
+        assert!(State::Const.finish(&Expression::Count).is_err());
+    }
+
+    #[test]
+    fn bounded_group_state() {
+        let columns = vec![String::from("name")];
+        let exprs = vec![Expression::Count];
+        let mut aggregation = Aggregation::new(&columns, &columns, None, exprs, 2).unwrap();
+        for name in ["ni", "si", "ni"] {
+            aggregation
+                .push(&vec![DataType::from_string(name)])
+                .unwrap();
+        }
+        match aggregation.push(&vec![DataType::from_string("ai")]) {
+            Ok(_) => panic!("expected too many groups"),
+            Err(e) => assert_eq!(e.message, "more than 2 groups"),
+        }
+
+        let exprs = vec![Expression::Values(String::from("name"))];
+        let mut aggregation = Aggregation::new(&columns, &[], None, exprs, 2).unwrap();
+        aggregation
+            .push(&vec![DataType::from_string("ni")])
+            .unwrap();
+        aggregation
+            .push(&vec![DataType::from_string("si")])
+            .unwrap();
+        assert!(aggregation
+            .push(&vec![DataType::from_string("ai")])
+            .is_err());
+
+        let height = vec![Expression::from_string("sum(height)").unwrap()];
+        assert!(Aggregation::new(&columns, &[], None, height, 2).is_err());
+    }
+}
@@ -0,0 +1,430 @@
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const MAX_PAYLOAD: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Opcode {
+    Continuation,
+    Text,
+    Binary,
+    Close,
+    Ping,
+    Pong,
+}
+
+impl Opcode {
+    fn from_u8(b: u8) -> Option<Opcode> {
+        match b {
+            0x0 => Some(Opcode::Continuation),
+            0x1 => Some(Opcode::Text),
+            0x2 => Some(Opcode::Binary),
+            0x8 => Some(Opcode::Close),
+            0x9 => Some(Opcode::Ping),
+            0xa => Some(Opcode::Pong),
+            _ => None,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            Opcode::Continuation => 0x0,
+            Opcode::Text => 0x1,
+            Opcode::Binary => 0x2,
+            Opcode::Close => 0x8,
+            Opcode::Ping => 0x9,
+            Opcode::Pong => 0xa,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub fin: bool,
+    pub opcode: Opcode,
+    pub payload: Vec<u8>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// joins a buffered reader and a writer, e.g. the two halves of a
+/// TcpStream, so the handshake and the frames share one read buffer
+pub struct Duplex<R, W> {
+    pub reader: R,
+    pub writer: W,
+}
+
+impl<R: Read, W> Read for Duplex<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R: BufRead, W> BufRead for Duplex<R, W> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl<R, W: Write> Write for Duplex<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// reads a single frame, unmasking the payload if needed
+pub fn read_frame(r: &mut impl Read) -> io::Result<Frame> {
+    let mut header = [0u8; 2];
+    r.read_exact(&mut header)?;
+
+    let fin = header[0] & 0x80 != 0;
+    let opcode = Opcode::from_u8(header[0] & 0x0f).ok_or_else(|| invalid("unknown opcode"))?;
+    let masked = header[1] & 0x80 != 0;
+
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut buf = [0u8; 2];
+            r.read_exact(&mut buf)?;
+            u16::from_be_bytes(buf) as u64
+        }
+        127 => {
+            let mut buf = [0u8; 8];
+            r.read_exact(&mut buf)?;
+            u64::from_be_bytes(buf)
+        }
+        n => n as u64,
+    };
+    if len > MAX_PAYLOAD {
+        return Err(invalid("frame too large"));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        r.read_exact(&mut mask)?;
+    }
+
+    let mut payload = vec![0u8; len as usize];
+    r.read_exact(&mut payload)?;
+    if masked {
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[i % 4];
+        }
+    }
+
+    Ok(Frame {
+        fin,
+        opcode,
+        payload,
+    })
+}
+
+/// writes a single final frame, clients must pass a mask
+pub fn write_frame(
+    w: &mut impl Write,
+    opcode: Opcode,
+    payload: &[u8],
+    mask: Option<[u8; 4]>,
+) -> io::Result<()> {
+    let mut buf = vec![0x80 | opcode.to_u8()];
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+
+    let len = payload.len();
+    if len < 126 {
+        buf.push(mask_bit | len as u8);
+    } else if len <= u16::MAX as usize {
+        buf.push(mask_bit | 126);
+        buf.extend((len as u16).to_be_bytes());
+    } else {
+        buf.push(mask_bit | 127);
+        buf.extend((len as u64).to_be_bytes());
+    }
+
+    match mask {
+        Some(mask) => {
+            buf.extend(mask);
+            buf.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        }
+        None => buf.extend(payload),
+    }
+
+    w.write_all(&buf)?;
+    w.flush()
+}
+
+/// a complete message received from the peer
+#[derive(Debug, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+/// reads frames until a complete data message arrives,
+/// answering pings on the way
+pub fn read_message(stream: &mut (impl Read + Write)) -> io::Result<Message> {
+    let mut opcode = None;
+    let mut data = vec![];
+
+    loop {
+        let frame = read_frame(stream)?;
+        match frame.opcode {
+            Opcode::Ping => {
+                write_frame(stream, Opcode::Pong, &frame.payload, None)?;
+                continue;
+            }
+            Opcode::Pong => continue,
+            Opcode::Close => return Ok(Message::Close),
+            Opcode::Continuation if opcode.is_none() => {
+                return Err(invalid("unexpected continuation frame"))
+            }
+            Opcode::Continuation => (),
+            op => opcode = Some(op),
+        }
+
+        data.extend(frame.payload);
+        if data.len() as u64 > MAX_PAYLOAD {
+            return Err(invalid("message too large"));
+        }
+        if frame.fin {
+            break;
+        }
+    }
+
+    match opcode {
+        Some(Opcode::Text) => String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| invalid("text message is not utf-8")),
+        _ => Ok(Message::Binary(data)),
+    }
+}
+
+/// reads the HTTP upgrade request and answers it, completing the handshake
+pub fn accept(stream: &mut (impl BufRead + Write)) -> io::Result<()> {
+    let mut key = None;
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line)?;
+    if !request_line.starts_with("GET ") {
+        return Err(invalid("expected a GET request"));
+    }
+
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of handshake"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(String::from(value.trim()));
+            }
+        }
+    }
+
+    let key = match key {
+        Some(key) => key,
+        None => {
+            stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+            return Err(invalid("missing Sec-WebSocket-Key"));
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    stream.flush()
+}
+
+/// sends the client side of the handshake and checks the answer
+pub fn connect(stream: &mut (impl BufRead + Write), host: &str, path: &str) -> io::Result<()> {
+    let key = "dGhlIHNhbXBsZSBub25jZQ==";
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n\
+         Sec-WebSocket-Version: 13\r\n\r\n",
+        path, host, key
+    )?;
+    stream.flush()?;
+
+    let mut accepted = false;
+    let mut status = String::new();
+    stream.read_line(&mut status)?;
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-accept") {
+                accepted = value.trim() == accept_key(key);
+            }
+        }
+    }
+
+    if !status.contains(" 101 ") || !accepted {
+        return Err(invalid("handshake rejected"));
+    }
+    Ok(())
+}
+
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for chunk in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([
+                chunk[i * 4],
+                chunk[i * 4 + 1],
+                chunk[i * 4 + 2],
+                chunk[i * 4 + 3],
+            ]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut out = [0u8; 20];
+    for (i, v) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&v.to_be_bytes());
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accept_key_from_rfc() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        for len in [5, 300, 70000] {
+            let payload = vec![b'x'; len];
+            let mut buf = vec![];
+            write_frame(&mut buf, Opcode::Text, &payload, Some([1, 2, 3, 4])).unwrap();
+
+            let frame = read_frame(&mut buf.as_slice()).unwrap();
+            assert_eq!(
+                frame,
+                Frame {
+                    fin: true,
+                    opcode: Opcode::Text,
+                    payload
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn handshake() {
+        let request =
+            "GET / HTTP/1.1\r\nHost: x\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let mut stream = Duplex {
+            reader: request.as_bytes(),
+            writer: vec![],
+        };
+
+        accept(&mut stream).unwrap();
+
+        let response = String::from_utf8(stream.writer).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+}